@@ -1,35 +1,87 @@
-use crate::models::{Agent, Team};
+use crate::arena::Arena;
+use crate::models::{Agent, Tactics};
 use crate::character_profiles::AgentProfile;
+use crate::perception::base_sight_radius;
+use crate::pathfinding::{find_path, REPLAN_DRIFT};
+use crate::terrain::Mobility;
+use rand::seq::SliceRandom;
 use rand::Rng;
+use std::collections::{HashMap, HashSet};
 use std::fs::OpenOptions;
 use std::io::Write;
 
+/// Random cells tried when picking where to search next.
+const SEARCH_GOAL_TRIES: usize = 20;
+
 impl Agent {
-    pub fn new_from_profile(id: usize, team: Team, x: i32, y: i32, profile: &AgentProfile) -> Self {
+    /// An agent of team `team`, which fights alone until it is given an alliance.
+    /// Stats that vary take their typical value; pass `AgentProfile::individual`
+    /// for a drawn one.
+    pub fn new_from_profile(id: usize, team: usize, tactics: Tactics, x: i32, y: i32, profile: &AgentProfile) -> Self {
         Agent {
             id,
             team,
+            member: 0,
+            alliance: team,
+            tactics,
             x,
             y,
-            hp: profile.hp,
-            str_: profile.str_,
-            spd: profile.spd,
-            def: profile.def,
-            base_damage: profile.base_damage,
-            crit_chance: profile.crit_chance,
+            hp: profile.hp.value(),
+            max_hp: profile.hp.value(),
+            str_: profile.str_.value(),
+            spd: profile.spd.value(),
+            def: profile.def.value(),
+            base_damage: profile.base_damage.value(),
+            crit_chance: profile.crit_chance.value(),
             alive: true,
             damage_dealt: 0,
+            kills: 0,
+            int_abstract: profile.int_abstract.value(),
+            squad_id: None,
+            order_target: None,
+            int_environmental: profile.int_environmental.value(),
+            sight_radius: base_sight_radius(profile.int_environmental.value()),
+            memory: HashMap::new(),
+            path: Vec::new(),
+            path_goal: None,
+            search_goal: None,
+            move_progress: 0.0,
+            endurance: profile.endurance.value(),
+            heat_tolerance: profile.heat_tolerance.value(),
+            cold_tolerance: profile.cold_tolerance.value(),
+            thermal_strain: 1.0,
+            mobility: Mobility::from_profile(profile),
         }
     }
 
     pub fn select_target(&self, agents: &[Agent]) -> Option<usize> {
         agents.iter()
             .enumerate()
-            .filter(|(_, a)| a.alive && a.team != self.team)
+            .filter(|(_, a)| a.alive && a.is_enemy_of(self))
             .min_by_key(|(_, a)| self.distance_to(a))
             .map(|(i, _)| i)
     }
 
+    /// Nearest enemy this agent currently sees or remembers, with the position
+    /// it believes that enemy to be at.
+    pub fn perceived_target(&self) -> Option<(usize, (i32, i32))> {
+        self.memory
+            .iter()
+            .min_by_key(|(&i, &(x, y))| ((self.x - x).abs() + (self.y - y).abs(), i))
+            .map(|(&i, &pos)| (i, pos))
+    }
+
+    /// Rough fighting strength: health times damage per hit before defence,
+    /// with strength counted as the agent's tactics count it (twice for solo
+    /// fighters, once in a group).
+    pub fn power(&self) -> f64 {
+        let strength_bonus = match self.tactics {
+            Tactics::Solo => self.str_ * 2,
+            Tactics::Group => self.str_,
+        };
+        self.max_hp as f64 * (self.base_damage + strength_bonus) as f64
+    }
+
     pub fn attack(&mut self, target: &mut Agent) -> (bool, i32) {
         let mut rng = rand::thread_rng();
         let hit_chance = 75 + (self.spd - target.spd) * 10;
@@ -85,17 +137,130 @@ impl Agent {
         (self.x - other.x).abs() + (self.y - other.y).abs()
     }
 
-    pub fn move_towards_coords(&mut self, tx: i32, ty: i32, occupied: &[(i32, i32)]) {
-        let dx = (tx - self.x).signum();
-        let dy = (ty - self.y).signum();
+    /// Next cell along a cheapest route over the terrain to (tx, ty), if it is
+    /// free. The route is cached and replanned when its next step is occupied,
+    /// when the goal has drifted more than `REPLAN_DRIFT` cells from where it
+    /// was planned to, or when no more than `REPLAN_DRIFT` steps are left.
+    pub fn plan_step(&mut self, tx: i32, ty: i32, occupied: &HashSet<(i32, i32)>, arena: &Arena) -> Option<(i32, i32)> {
+        let goal = (tx, ty);
+        let stale = match (self.path_goal, self.path.last()) {
+            (Some(planned), Some(next)) => {
+                occupied.contains(next)
+                    || (planned.0 - tx).abs() + (planned.1 - ty).abs() > REPLAN_DRIFT
+                    || self.path.len() as i32 <= REPLAN_DRIFT
+            }
+            _ => true,
+        };
+        if stale {
+            self.path = find_path((self.x, self.y), goal, occupied, |from, to| {
+                arena.step_cost(from, to, &self.mobility)
+            });
+            self.path_goal = Some(goal);
+        }
+
+        self.path.last().copied().filter(|next| !occupied.contains(next))
+    }
 
-        let options = vec![(self.x + dx, self.y), (self.x, self.y + dy)];
-        for (nx, ny) in options {
-            if !occupied.contains(&(nx, ny)) {
-                self.x = nx;
-                self.y = ny;
-                break;
+    /// Moves one cell towards (tx, ty), keeping `occupied` in sync so no two
+    /// living agents ever share a cell. `move_cost` scales the terrain's cost.
+    pub fn move_towards_coords(
+        &mut self,
+        tx: i32,
+        ty: i32,
+        occupied: &mut HashSet<(i32, i32)>,
+        arena: &Arena,
+        move_cost: f32,
+    ) {
+        if let Some(next) = self.plan_step(tx, ty, occupied, arena) {
+            if self.ready_to_move(self.step_cost(next, arena, move_cost)) {
+                self.step_to(next, occupied);
             }
         }
     }
-} 
\ No newline at end of file
+
+    /// Rounds of movement needed to step into `next`: the terrain's cost,
+    /// including any climb, scaled by `move_cost`.
+    pub fn step_cost(&self, next: (i32, i32), arena: &Arena, move_cost: f32) -> f32 {
+        arena.step_cost((self.x, self.y), next, &self.mobility).unwrap_or(1) as f32 * move_cost
+    }
+
+    /// Builds up one round of movement and reports whether it covers a step
+    /// costing `move_cost`. Costs above 1 make agents skip some moves.
+    pub fn ready_to_move(&mut self, move_cost: f32) -> bool {
+        self.move_progress += 1.0;
+        if self.move_progress >= move_cost {
+            self.move_progress -= move_cost;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Next cell of a sweep across the arena, for agents that know of no enemy
+    /// to head for. The agent heads straight for a random cell out of sight,
+    /// and picks another once it can see that one or can get no closer to it.
+    pub fn search_step(&mut self, occupied: &HashSet<(i32, i32)>, arena: &Arena) -> Option<(i32, i32)> {
+        let seen = |(x, y): (i32, i32)| (self.x - x).abs() + (self.y - y).abs() <= self.sight_radius.max(1);
+        if self.search_goal.is_none_or(seen) {
+            self.search_goal = self.pick_search_goal(arena);
+        }
+        let (gx, gy) = self.search_goal?;
+        let distance = |(x, y): (i32, i32)| (gx - x).abs() + (gy - y).abs();
+        // Searches are cheap greedy steps rather than routes, as a crowd
+        // sweeping the arena would otherwise replan around itself every round
+        let closer: Vec<(i32, i32)> = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+            .into_iter()
+            .map(|(dx, dy)| (self.x + dx, self.y + dy))
+            .filter(|&cell| distance(cell) < distance((self.x, self.y)) && arena.is_passable(cell, &self.mobility))
+            .collect();
+        if closer.is_empty() {
+            self.search_goal = None;
+        }
+        closer.choose(&mut rand::thread_rng()).copied().filter(|cell| !occupied.contains(cell))
+    }
+
+    /// A random cell the agent can stand on and can't see from where it is.
+    fn pick_search_goal(&self, arena: &Arena) -> Option<(i32, i32)> {
+        let mut rng = rand::thread_rng();
+        (0..SEARCH_GOAL_TRIES)
+            .map(|_| (rng.gen_range(0..arena.width()), rng.gen_range(0..arena.height())))
+            .find(|&(x, y)| {
+                (self.x - x).abs() + (self.y - y).abs() > self.sight_radius && arena.is_passable((x, y), &self.mobility)
+            })
+    }
+
+    /// Takes a step of the sweep when the agent knows of no enemy to head for.
+    pub fn search(&mut self, occupied: &mut HashSet<(i32, i32)>, arena: &Arena, move_cost: f32) {
+        if let Some(next) = self.search_step(occupied, arena) {
+            if self.ready_to_move(self.step_cost(next, arena, move_cost)) {
+                self.step_to(next, occupied);
+            }
+        }
+    }
+
+    /// Moves into `next` and updates `occupied`. Stepping off the cached route discards it.
+    pub fn step_to(&mut self, next: (i32, i32), occupied: &mut HashSet<(i32, i32)>) {
+        if self.path.last() == Some(&next) {
+            self.path.pop();
+        } else {
+            self.path.clear();
+        }
+        occupied.remove(&(self.x, self.y));
+        occupied.insert(next);
+        self.x = next.0;
+        self.y = next.1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn power_counts_strength_as_the_tactics_do() {
+        let group = Agent { tactics: Tactics::Group, max_hp: 100, base_damage: 10, str_: 5, ..Agent::default() };
+        let solo = Agent { tactics: Tactics::Solo, ..group.clone() };
+        assert_eq!(group.power(), 1500.0);
+        assert_eq!(solo.power(), 2000.0);
+    }
+}
@@ -1,9 +1,9 @@
-use crate::models::{BattleResult, Team};
+use crate::models::{BattleResult, SoloResult, Tactics, DRAW, TIMEOUT};
 use serde_json::from_reader;
 use std::fs::File;
 use std::io::{BufReader, Write};
 use std::path::Path;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 pub fn load_results_from_file<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<BattleResult>> {
     let file = File::open(&path)?;
@@ -12,52 +12,463 @@ pub fn load_results_from_file<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<Ba
     Ok(results)
 }
 
+/// Light level buckets and their exclusive upper bounds.
+const LIGHT_BUCKETS: [(&str, f64); 4] = [
+    ("Dark (0-0.25)", 0.25),
+    ("Dim (0.25-0.5)", 0.5),
+    ("Low (0.5-0.75)", 0.75),
+    ("Bright (0.75-1)", f64::INFINITY),
+];
+
+/// Temperature buckets (°C) and their exclusive upper bounds.
+const TEMPERATURE_BUCKETS: [(&str, f64); 5] = [
+    ("Freezing (<0°C)", 0.0),
+    ("Cold (0-15°C)", 15.0),
+    ("Mild (15-25°C)", 25.0),
+    ("Warm (25-35°C)", 35.0),
+    ("Hot (35°C+)", f64::INFINITY),
+];
+
+/// Correlations weaker than this either way are too faint to name a driver.
+const WEAK_CORRELATION: f64 = 0.1;
+
+/// Whether a team fighting with solo tactics won. Older results only name the winner.
+fn solo_won(r: &BattleResult) -> bool {
+    if r.teams.is_empty() {
+        r.winner == "Solo"
+    } else {
+        r.teams.iter().any(|t| t.won && t.tactics == Tactics::Solo)
+    }
+}
+
+/// Per-team totals across a batch.
+#[derive(Default)]
+struct TeamTotals {
+    battles: u32,
+    wins: u32,
+    count: u32,
+    survivors: u32,
+    damage_dealt: u32,
+    /// Each battle's average and strongest individual power, and whether the team won.
+    strength: Vec<(f64, f64, bool)>,
+}
+
+/// Totals for one profile within one team across a batch.
+#[derive(Default)]
+struct MemberTotals {
+    battles: u32,
+    count: u32,
+    survivors: u32,
+    damage_dealt: u32,
+    team_damage: u32,
+    /// Damage dealt by the member, and by its whole team, in battles the team won.
+    damage_in_wins: u32,
+    team_damage_in_wins: u32,
+}
+
+/// Totals for one team's reinforcements of one profile across a batch.
+#[derive(Default)]
+struct ArrivalTotals {
+    arrivals: u32,
+    count: u32,
+    survivors: u32,
+    team_standing: u32,
+    /// Arrivals that found none of their team left standing.
+    too_late: u32,
+    /// Arrivals in battles their team went on to win.
+    in_wins: u32,
+}
+
 pub fn run_analysis(results: &[BattleResult], batch_id: usize) {
     let total = results.len() as f64;
-    let group_wins = results.iter().filter(|r| r.winner == Team::Group).count() as f64;
-    let solo_wins = results.iter().filter(|r| r.winner == Team::Solo).count() as f64;
+    let draws = results.iter().filter(|r| r.winner == DRAW).count() as f64;
+    let timeouts = results.iter().filter(|r| r.winner == TIMEOUT).count() as f64;
     let avg_casualties = results.iter().map(|r| r.group_casualties).sum::<u32>() as f64 / total;
     let avg_rounds = results.iter().map(|r| r.rounds).sum::<u32>() as f64 / total;
+    let avg_seconds = results.iter().map(|r| r.elapsed_seconds).sum::<f64>() / total;
 
+    let mut engine_counts = HashMap::new();
+    let mut mode_counts = HashMap::new();
     let mut climate_counts = HashMap::new();
     let mut weather_counts = HashMap::new();
-    let mut day_count = 0;
+    let mut end_weather_counts = HashMap::new();
+    let mut weather_changed = (0, 0);
+    let mut map_counts = HashMap::new();
+    let mut density_counts = BTreeMap::new();
+    let mut solo_count_counts = BTreeMap::new();
+    let mut formation_counts = HashMap::new();
+    let mut phase_counts = HashMap::new();
+    let mut light_buckets = [(0, 0); LIGHT_BUCKETS.len()];
+    let mut temperature_buckets = [(0, 0); TEMPERATURE_BUCKETS.len()];
+    // Keyed by name in the order teams first appear
+    let mut team_totals: Vec<(String, TeamTotals)> = Vec::new();
+    let mut member_totals: Vec<(String, MemberTotals)> = Vec::new();
+    let mut arrival_totals: Vec<(String, ArrivalTotals)> = Vec::new();
+    let mixed_teams = results.iter().any(|r| r.teams.iter().any(|t| t.members.len() > 1));
 
     for r in results {
-        *climate_counts.entry(&r.context.climate).or_insert(0) += 1;
-        *weather_counts.entry(&r.context.weather).or_insert(0) += 1;
-        if r.context.is_day {
-            day_count += 1;
+        if r.teams.is_empty() {
+            // Older results only name the winner of a Group vs Solo battle
+            for name in ["Group", "Solo"] {
+                let i = team_index(&mut team_totals, name);
+                team_totals[i].1.wins += (r.winner == name) as u32;
+            }
+        }
+        for team in &r.teams {
+            let i = team_index(&mut team_totals, &team.name);
+            let totals = &mut team_totals[i].1;
+            totals.battles += 1;
+            totals.wins += team.won as u32;
+            totals.count += team.count;
+            totals.survivors += team.survivors;
+            totals.damage_dealt += team.damage_dealt;
+            totals.strength.push((team.mean_power, team.top_power, team.won));
+            for member in &team.members {
+                let i = team_index(&mut member_totals, &format!("{} ({})", member.profile, team.name));
+                let totals = &mut member_totals[i].1;
+                totals.battles += 1;
+                totals.count += member.count;
+                totals.survivors += member.survivors;
+                totals.damage_dealt += member.damage_dealt;
+                totals.team_damage += team.damage_dealt;
+                if team.won {
+                    totals.damage_in_wins += member.damage_dealt;
+                    totals.team_damage_in_wins += team.damage_dealt;
+                }
+            }
+        }
+
+        for arrival in &r.arrivals {
+            let i = team_index(&mut arrival_totals, &format!("{} ({})", arrival.profile, arrival.team));
+            let totals = &mut arrival_totals[i].1;
+            totals.arrivals += 1;
+            totals.count += arrival.count;
+            totals.survivors += arrival.survivors;
+            totals.team_standing += arrival.team_standing;
+            totals.too_late += (arrival.team_standing == 0) as u32;
+            totals.in_wins += r.teams.iter().any(|t| t.won && t.name == arrival.team) as u32;
+        }
+
+        *engine_counts.entry(format!("{:?}", r.engine)).or_insert(0) += 1;
+        *mode_counts.entry(format!("{:?}", r.round_mode)).or_insert(0) += 1;
+        let solo_win = solo_won(r) as u32;
+        let climate = climate_counts.entry(&r.context.climate).or_insert((0, 0));
+        climate.0 += 1;
+        climate.1 += solo_win;
+        let weather = weather_counts.entry(&r.context.weather).or_insert((0, 0));
+        weather.0 += 1;
+        weather.1 += solo_win;
+        let end_weather = r.weather_timeline.last().map_or(&r.context.weather, |change| &change.weather);
+        let end_weather = end_weather_counts.entry(end_weather).or_insert((0, 0));
+        end_weather.0 += 1;
+        end_weather.1 += solo_win;
+        if r.weather_timeline.len() > 1 {
+            weather_changed.0 += 1;
+            weather_changed.1 += solo_win;
+        }
+        // Older results don't list their solos
+        if !r.solos.is_empty() {
+            let solo_count = solo_count_counts.entry(r.solos.len()).or_insert((0, 0));
+            solo_count.0 += 1;
+            solo_count.1 += solo_win;
         }
+        let map = map_counts.entry(&r.map).or_insert((0, 0));
+        map.0 += 1;
+        map.1 += solo_win;
+        let density = density_counts.entry(format!("{:.4}", r.density)).or_insert((0, 0));
+        density.0 += 1;
+        density.1 += solo_win;
+        let formation = formation_counts
+            .entry(format!("{:?} vs {:?}", r.group_formation, r.solo_formation))
+            .or_insert((0, 0));
+        formation.0 += 1;
+        formation.1 += solo_win;
+        let phase = phase_counts.entry(format!("{:?}", r.context.light)).or_insert((0, 0));
+        phase.0 += 1;
+        phase.1 += solo_win;
+        let bucket = LIGHT_BUCKETS
+            .iter()
+            .position(|(_, upper)| r.context.light_level < *upper)
+            .unwrap_or(LIGHT_BUCKETS.len() - 1);
+        light_buckets[bucket].0 += 1;
+        light_buckets[bucket].1 += solo_win;
+        let bucket = TEMPERATURE_BUCKETS
+            .iter()
+            .position(|(_, upper)| r.context.temperature_c < *upper)
+            .unwrap_or(TEMPERATURE_BUCKETS.len() - 1);
+        temperature_buckets[bucket].0 += 1;
+        temperature_buckets[bucket].1 += solo_win;
     }
 
     let mut output = String::new();
     output.push_str("== Analysis Summary ==\n");
     output.push_str(&format!("Total Battles: {}\n\n", results.len()));
     output.push_str("Wins:\n");
-    output.push_str(&format!("- Group: {:.1}%\n", (group_wins / total) * 100.0));
-    output.push_str(&format!("- Solo: {:.1}%\n\n", (solo_wins / total) * 100.0));
+    for (name, totals) in &team_totals {
+        output.push_str(&format!("- {}: {:.1}%\n", name, (totals.wins as f64 / total) * 100.0));
+    }
+    if draws > 0.0 {
+        output.push_str(&format!("- Draw: {:.1}%\n", (draws / total) * 100.0));
+    }
+    if timeouts > 0.0 {
+        output.push_str(&format!("- Timeout: {:.1}%\n", (timeouts / total) * 100.0));
+    }
+    output.push('\n');
+
+    let with_teams: Vec<&(String, TeamTotals)> = team_totals.iter().filter(|(_, t)| t.battles > 0).collect();
+    if !with_teams.is_empty() {
+        output.push_str("Teams:\n");
+        for (name, totals) in with_teams {
+            let battles = totals.battles as f64;
+            output.push_str(&format!(
+                "- {}: {:.1} of {:.1} survive, {:.1} damage dealt\n",
+                name,
+                totals.survivors as f64 / battles,
+                totals.count as f64 / battles,
+                totals.damage_dealt as f64 / battles
+            ));
+        }
+        output.push('\n');
+    }
+
+    if mixed_teams {
+        output.push_str("Profiles (Team):\n");
+        for (name, totals) in &member_totals {
+            let battles = totals.battles as f64;
+            output.push_str(&format!(
+                "- {}: {:.1} of {:.1} die, {:.1}% of team damage, {:.1}% of team damage in wins\n",
+                name,
+                (totals.count - totals.survivors) as f64 / battles,
+                totals.count as f64 / battles,
+                percent(totals.damage_dealt, totals.team_damage),
+                percent(totals.damage_in_wins, totals.team_damage_in_wins)
+            ));
+        }
+        output.push('\n');
+    }
+
+    if !arrival_totals.is_empty() {
+        output.push_str("Reinforcements (Team):\n");
+        for (name, totals) in &arrival_totals {
+            let arrivals = totals.arrivals as f64;
+            output.push_str(&format!(
+                "- {}: {:.1} arrivals per battle, {:.1}% survive, {:.1} of their team standing on arrival, {:.1}% arrive too late, {:.1}% arrive for a win\n",
+                name,
+                arrivals / total,
+                percent(totals.survivors, totals.count),
+                totals.team_standing as f64 / arrivals,
+                percent(totals.too_late, totals.arrivals),
+                percent(totals.in_wins, totals.arrivals)
+            ));
+        }
+        output.push('\n');
+    }
+
+    // Only worth showing once individuals differ from battle to battle
+    let varied: Vec<&(String, TeamTotals)> = team_totals
+        .iter()
+        .filter(|(_, t)| t.strength.iter().any(|&(mean, _, _)| mean != t.strength[0].0))
+        .collect();
+    if !varied.is_empty() {
+        output.push_str("Individual Strength (correlation with winning):\n");
+        for (name, totals) in varied {
+            let won: Vec<f64> = totals.strength.iter().map(|&(_, _, won)| won as u8 as f64).collect();
+            let mean: Vec<f64> = totals.strength.iter().map(|&(mean, _, _)| mean).collect();
+            let top: Vec<f64> = totals.strength.iter().map(|&(_, top, _)| top).collect();
+            let (average, strongest) = (correlation(&mean, &won), correlation(&top, &won));
+            let (a, s) = (average.unwrap_or(0.0).abs(), strongest.unwrap_or(0.0).abs());
+            let verdict = if a.max(s) < WEAK_CORRELATION {
+                "no clear driver"
+            } else if a >= s {
+                "the average drives outcomes"
+            } else {
+                "the strongest individuals drive outcomes"
+            };
+            output.push_str(&format!(
+                "- {}: average {}, strongest {} ({})\n",
+                name,
+                format_correlation(average),
+                format_correlation(strongest),
+                verdict
+            ));
+        }
+        output.push('\n');
+    }
 
     output.push_str(&format!("Average Group Casualties: {:.1}\n", avg_casualties));
-    output.push_str(&format!("Average Rounds: {:.1}\n\n", avg_rounds));
+    output.push_str(&format!("Average Rounds: {:.1}\n", avg_rounds));
+    output.push_str(&format!("Average Duration: {:.1}s\n\n", avg_seconds));
+
+    output.push_str("Engine:\n");
+    for (engine, count) in &engine_counts {
+        output.push_str(&format!("- {}: {:.1}%\n", engine, (*count as f64 / total) * 100.0));
+    }
+    output.push('\n');
 
+    output.push_str("Round Mode:\n");
+    for (mode, count) in &mode_counts {
+        output.push_str(&format!("- {}: {:.1}%\n", mode, (*count as f64 / total) * 100.0));
+    }
+    let mutual_kills = results.iter().map(|r| r.causal.mutual_kills).sum::<u32>();
+    if mutual_kills > 0 {
+        output.push_str(&format!("- Mutual Kills per Battle: {:.2}\n", mutual_kills as f64 / total));
+    }
+    output.push('\n');
+
+    let squad_battles: Vec<&BattleResult> = results.iter().filter(|r| r.causal.squads > 0).collect();
+    if !squad_battles.is_empty() {
+        let n = squad_battles.len() as f64;
+        let avg_squads = squad_battles.iter().map(|r| r.causal.squads).sum::<u32>() as f64 / n;
+        let avg_leaders_killed = squad_battles.iter().map(|r| r.causal.leaders_killed).sum::<u32>() as f64 / n;
+        let avg_orders = squad_battles.iter().map(|r| r.causal.orders_issued).sum::<u32>() as f64 / n;
+        output.push_str("Command:\n");
+        output.push_str(&format!("- Squads: {:.1}\n", avg_squads));
+        output.push_str(&format!("- Leaders Killed: {:.1}\n", avg_leaders_killed));
+        output.push_str(&format!("- Orders Issued: {:.1}\n\n", avg_orders));
+    }
+
+    let solo_battles: Vec<&BattleResult> = results.iter().filter(|r| !r.solos.is_empty()).collect();
+    if !solo_battles.is_empty() {
+        let n = solo_battles.len() as f64;
+        let solos = solo_battles.iter().map(|r| r.solos.len()).sum::<usize>() as f64 / n;
+        let alive = solo_battles.iter().map(|r| r.solos_alive).sum::<u32>() as f64 / n;
+        let end_hp = solo_battles.iter().flat_map(|r| &r.solos).map(|s| s.end_hp).sum::<u32>();
+        let max_hp = solo_battles.iter().flat_map(|r| &r.solos).map(|s| s.max_hp).sum::<u32>();
+        let kills = solo_battles.iter().flat_map(|r| &r.solos).map(|s| s.kills).sum::<u32>();
+        output.push_str("Solos:\n");
+        output.push_str(&format!("- Alive at End: {:.2} of {:.2}\n", alive, solos));
+        output.push_str(&format!("- HP Left: {:.1}%\n", percent(end_hp, max_hp)));
+        output.push_str(&format!("- Kills per Battle: {:.1}\n", kills as f64 / n));
+        // How evenly solos split the kills when several fight
+        let shared: Vec<&&BattleResult> = solo_battles
+            .iter()
+            .filter(|r| r.solos.len() > 1 && r.solos.iter().any(|s| s.kills > 0))
+            .collect();
+        if !shared.is_empty() {
+            let top_share = shared
+                .iter()
+                .map(|r| {
+                    let top = r.solos.iter().map(|s| s.kills).max().unwrap_or(0);
+                    percent(top, r.solos.iter().map(|s| s.kills).sum())
+                })
+                .sum::<f64>()
+                / shared.len() as f64;
+            let even_share = shared.iter().map(|r| 100.0 / r.solos.len() as f64).sum::<f64>() / shared.len() as f64;
+            output.push_str(&format!(
+                "- Top Solo's Share of Kills: {:.1}% (an even split would be {:.1}%)\n",
+                top_share, even_share
+            ));
+        }
+        output.push('\n');
+    }
+
+    let slips = results.iter().map(|r| r.causal.slips).sum::<u32>();
+    let slips_per_agent = results.iter().map(|r| r.causal.slips_per_agent as f64).sum::<f64>();
+    output.push_str(&format!(
+        "Average Slips: {:.1} ({:.2} per agent)\n",
+        slips as f64 / total,
+        slips_per_agent / total
+    ));
+    // Share of solo agents, or of battles for results without per-solo records
+    let solos: Vec<&SoloResult> = results.iter().flat_map(|r| &r.solos).collect();
+    let solo_up_high = if solos.is_empty() {
+        results.iter().filter(|r| r.causal.solo_end_elevation > 0).count() as f64 / total
+    } else {
+        solos.iter().filter(|s| s.end_elevation > 0).count() as f64 / solos.len() as f64
+    };
+    output.push_str(&format!("Solo Finished Up High: {:.1}%\n\n", solo_up_high * 100.0));
+
+    // Each breakdown shows how often the condition came up and how the solo side fared under it
     output.push_str("Climate Breakdown:\n");
-    for (climate, count) in &climate_counts {
-        output.push_str(&format!("- {}: {:.1}%\n", climate, (*count as f64 / total) * 100.0));
+    for (climate, (count, solo_wins)) in &climate_counts {
+        output.push_str(&breakdown_line(climate, *count, *solo_wins, total));
     }
 
     output.push_str("\nWeather Breakdown:\n");
-    for (weather, count) in &weather_counts {
-        output.push_str(&format!("- {}: {:.1}%\n", weather, (*count as f64 / total) * 100.0));
+    for (weather, (count, solo_wins)) in &weather_counts {
+        output.push_str(&breakdown_line(weather, *count, *solo_wins, total));
+    }
+
+    output.push_str("\nWeather at the End:\n");
+    for (weather, (count, solo_wins)) in &end_weather_counts {
+        output.push_str(&breakdown_line(weather, *count, *solo_wins, total));
+    }
+    output.push_str(&breakdown_line("Changed Mid-Battle", weather_changed.0, weather_changed.1, total));
+    let changes = results.iter().map(|r| r.weather_timeline.len().saturating_sub(1)).sum::<usize>();
+    output.push_str(&format!("- Average Changes: {:.2}\n", changes as f64 / total));
+
+    output.push_str("\nMap:\n");
+    for (map, (count, solo_wins)) in &map_counts {
+        output.push_str(&breakdown_line(map, *count, *solo_wins, total));
+    }
+
+    output.push_str("\nFormation (Group vs Solo):\n");
+    for (formation, (count, solo_wins)) in &formation_counts {
+        output.push_str(&breakdown_line(formation, *count, *solo_wins, total));
+    }
+    let separation = results.iter().map(|r| r.causal.start_separation).sum::<u32>() as f64 / total;
+    output.push_str(&format!("- Average Starting Separation: {:.1}\n", separation));
+
+    if !solo_count_counts.is_empty() {
+        output.push_str("\nSolo Count:\n");
+        for (solo_count, (count, solo_wins)) in &solo_count_counts {
+            output.push_str(&breakdown_line(&solo_count.to_string(), *count, *solo_wins, total));
+        }
     }
 
-    output.push_str("\nDay/Night:\n");
-    output.push_str(&format!("- Day: {:.1}%\n", (day_count as f64 / total) * 100.0));
-    output.push_str(&format!("- Night: {:.1}%\n", 100.0 - (day_count as f64 / total) * 100.0));
+    output.push_str("\nDensity (agents per cell):\n");
+    for (density, (count, solo_wins)) in &density_counts {
+        output.push_str(&breakdown_line(density, *count, *solo_wins, total));
+    }
+
+    output.push_str("\nTemperature:\n");
+    for ((label, _), (count, solo_wins)) in TEMPERATURE_BUCKETS.iter().zip(temperature_buckets) {
+        output.push_str(&breakdown_line(label, count, solo_wins, total));
+    }
+
+    output.push_str("\nLight Phase:\n");
+    for (phase, (count, solo_wins)) in &phase_counts {
+        output.push_str(&breakdown_line(phase, *count, *solo_wins, total));
+    }
+
+    output.push_str("\nLight Level:\n");
+    for ((label, _), (count, solo_wins)) in LIGHT_BUCKETS.iter().zip(light_buckets) {
+        output.push_str(&breakdown_line(label, count, solo_wins, total));
+    }
 
     let filename = format!("analysis_batch_{}.txt", batch_id);
     let mut file = File::create(&filename).expect("Failed to create analysis file");
     file.write_all(output.as_bytes()).expect("Failed to write analysis file");
 
     println!("Analysis written to {}", filename);
-} 
\ No newline at end of file
+} 
+/// Position of `name` in `totals`, adding it if it's new.
+fn team_index<T: Default>(totals: &mut Vec<(String, T)>, name: &str) -> usize {
+    totals.iter().position(|(n, _)| n == name).unwrap_or_else(|| {
+        totals.push((name.to_string(), T::default()));
+        totals.len() - 1
+    })
+}
+
+/// Pearson correlation, or `None` when either side never varies.
+fn correlation(xs: &[f64], ys: &[f64]) -> Option<f64> {
+    let n = xs.len() as f64;
+    let (mean_x, mean_y) = (xs.iter().sum::<f64>() / n, ys.iter().sum::<f64>() / n);
+    let covariance: f64 = xs.iter().zip(ys).map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let spread_x: f64 = xs.iter().map(|x| (x - mean_x).powi(2)).sum();
+    let spread_y: f64 = ys.iter().map(|y| (y - mean_y).powi(2)).sum();
+    (spread_x > 0.0 && spread_y > 0.0).then(|| covariance / (spread_x * spread_y).sqrt())
+}
+
+fn format_correlation(r: Option<f64>) -> String {
+    r.map_or("n/a".to_string(), |r| format!("{:+.2}", r))
+}
+
+fn percent(part: u32, whole: u32) -> f64 {
+    if whole > 0 { part as f64 / whole as f64 * 100.0 } else { 0.0 }
+}
+
+fn breakdown_line(label: &str, count: u32, solo_wins: u32, total: f64) -> String {
+    let solo_rate = if count > 0 { solo_wins as f64 / count as f64 * 100.0 } else { 0.0 };
+    format!("- {}: {:.1}% (Solo wins {:.1}%)\n", label, (count as f64 / total) * 100.0, solo_rate)
+}
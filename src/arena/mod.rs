@@ -1,17 +1,100 @@
 use crate::models::Agent;
+use crate::terrain::{Mobility, Terrain};
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::f64::consts::PI;
+use std::fs;
+use std::path::Path;
 use rand::Rng;
 
-pub const ARENA_WIDTH: i32 = 100;
-pub const ARENA_HEIGHT: i32 = 100;
+pub const DEFAULT_ARENA_WIDTH: i32 = 100;
+pub const DEFAULT_ARENA_HEIGHT: i32 = 100;
 
-/// Returns true if the (x, y) coordinate is within the 50x50 arena bounds
-pub fn is_within_bounds(x: i32, y: i32) -> bool {
-    x >= 0 && x < ARENA_WIDTH && y >= 0 && y < ARENA_HEIGHT
+/// Outline of the arena. Cells outside it are walled off.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum ArenaShape {
+    #[default]
+    Rectangle,
+    /// The largest circle, or ellipse, that fits the arena.
+    Circle,
+    /// A strip `width` cells across, running the length of the arena through its middle.
+    Corridor { width: i32 },
+    /// A map file the size of the arena whose walls mark the cells outside it.
+    Mask { path: String },
+}
+
+/// Size and shape of the arena, as set in a scenario.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArenaConfig {
+    pub width: i32,
+    pub height: i32,
+    pub shape: ArenaShape,
+    /// Agents per usable cell. When set the arena is resized to match,
+    /// keeping its proportions (a corridor only grows longer).
+    pub density: Option<f64>,
+}
+
+impl Default for ArenaConfig {
+    fn default() -> Self {
+        ArenaConfig {
+            width: DEFAULT_ARENA_WIDTH,
+            height: DEFAULT_ARENA_HEIGHT,
+            shape: ArenaShape::Rectangle,
+            density: None,
+        }
+    }
+}
+
+impl ArenaConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width < 1 || self.height < 1 {
+            return Err(format!("arena must be at least 1x1, got {}x{}", self.width, self.height));
+        }
+        if let ArenaShape::Corridor { width } = self.shape {
+            if !(1..=self.height).contains(&width) {
+                return Err(format!("corridor width must be between 1 and the arena height {}", self.height));
+            }
+        }
+        if let Some(density) = self.density {
+            if !(density > 0.0 && density <= 1.0) {
+                return Err(format!("density must be above 0 and at most 1, got {}", density));
+            }
+            if matches!(self.shape, ArenaShape::Mask { .. }) {
+                return Err("density can't be used with a mask, which has a fixed size".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// The arena dimensions to use for `agents` agents, after applying `density`.
+    pub fn sized_for(&self, agents: usize) -> ArenaConfig {
+        let Some(density) = self.density else {
+            return self.clone();
+        };
+        let target = (agents as f64 / density).ceil();
+        let (width, height) = (self.width as f64, self.height as f64);
+        let (width, height) = match self.shape {
+            ArenaShape::Corridor { width: across } => ((target / across as f64).ceil(), height),
+            ArenaShape::Circle => {
+                let scale = (target / (PI / 4.0 * width * height)).sqrt();
+                ((width * scale).ceil(), (height * scale).ceil())
+            }
+            _ => {
+                let scale = (target / (width * height)).sqrt();
+                ((width * scale).ceil(), (height * scale).ceil())
+            }
+        };
+        ArenaConfig {
+            width: (width as i32).max(1),
+            height: (height as i32).max(1),
+            ..self.clone()
+        }
+    }
 }
 
 /// Returns a HashSet of positions currently occupied by alive agents
-pub fn get_occupied_positions(agents: &Vec<Agent>) -> HashSet<(i32, i32)> {
+pub fn get_occupied_positions(agents: &[Agent]) -> HashSet<(i32, i32)> {
     agents
         .iter()
         .filter(|a| a.alive)
@@ -22,32 +105,268 @@ pub fn get_occupied_positions(agents: &Vec<Agent>) -> HashSet<(i32, i32)> {
 pub struct Arena {
     width: i32,
     height: i32,
+    /// Map the terrain came from, "open" for a bare arena.
+    name: String,
+    /// Row-major, `width` cells per row.
+    cells: Vec<Terrain>,
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Arena {
     pub fn new() -> Self {
+        Self::open(DEFAULT_ARENA_WIDTH, DEFAULT_ARENA_HEIGHT)
+    }
+
+    /// Bare ground of the given size.
+    pub fn open(width: i32, height: i32) -> Self {
         Arena {
-            width: 100,
-            height: 100,
+            width,
+            height,
+            name: "open".to_string(),
+            cells: vec![Terrain::Open; (width * height) as usize],
+        }
+    }
+
+    /// Builds the arena for a scenario: the map if there is one, otherwise bare
+    /// ground of the configured size, then walls off everything outside the shape.
+    pub fn build(config: &ArenaConfig, map: Option<&str>) -> Result<Self, String> {
+        let mut arena = match map {
+            Some(path) => Arena::load(path)?,
+            None => Arena::open(config.width, config.height),
+        };
+        arena.apply_shape(&config.shape)?;
+        Ok(arena)
+    }
+
+    fn apply_shape(&mut self, shape: &ArenaShape) -> Result<(), String> {
+        let (width, height) = (self.width, self.height);
+        let outside: Box<dyn Fn((i32, i32)) -> bool> = match shape {
+            ArenaShape::Rectangle => return Ok(()),
+            ArenaShape::Circle => Box::new(move |(x, y)| {
+                let dx = (x as f64 + 0.5) / width as f64 * 2.0 - 1.0;
+                let dy = (y as f64 + 0.5) / height as f64 * 2.0 - 1.0;
+                dx * dx + dy * dy > 1.0
+            }),
+            ArenaShape::Corridor { width: across } => {
+                let top = (height - across) / 2;
+                let across = *across;
+                Box::new(move |(_, y)| y < top || y >= top + across)
+            }
+            ArenaShape::Mask { path } => {
+                let mask = Arena::load(path)?;
+                if (mask.width, mask.height) != (width, height) {
+                    return Err(format!(
+                        "mask {} is {}x{} but the arena is {}x{}",
+                        path, mask.width, mask.height, width, height
+                    ));
+                }
+                Box::new(move |cell| mask.terrain_at(cell) == Terrain::Wall)
+            }
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                if outside((x, y)) {
+                    self.cells[(y * width + x) as usize] = Terrain::Wall;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads a terrain map. The map is named after the file.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("could not open map {}: {}", path, e))?;
+        let name = Path::new(path)
+            .file_stem()
+            .map_or_else(|| path.to_string(), |stem| stem.to_string_lossy().into_owned());
+        Arena::from_map(&name, &text).map_err(|e| format!("invalid map {}: {}", path, e))
+    }
+
+    /// Parses a text map with one row per line and one symbol per cell (see
+    /// `Terrain::from_symbol`). Commas and spaces between symbols are ignored,
+    /// so comma-separated maps load too.
+    /// The arena takes the map's size; every row must be the same length.
+    pub fn from_map(name: &str, text: &str) -> Result<Self, String> {
+        let mut cells = Vec::new();
+        let mut width = 0;
+        let mut height = 0;
+        for (row, line) in text.lines().enumerate() {
+            let symbols: Vec<char> = line.chars().filter(|c| *c != ',' && !c.is_whitespace()).collect();
+            if symbols.is_empty() {
+                continue;
+            }
+            if height == 0 {
+                width = symbols.len();
+            } else if symbols.len() != width {
+                return Err(format!("row {} has {} cells, expected {}", row + 1, symbols.len(), width));
+            }
+            for symbol in symbols {
+                let terrain = Terrain::from_symbol(symbol)
+                    .ok_or_else(|| format!("unknown terrain '{}' on row {}", symbol, row + 1))?;
+                cells.push(terrain);
+            }
+            height += 1;
+        }
+        if height == 0 {
+            return Err("map is empty".to_string());
+        }
+
+        Ok(Arena {
+            width: width as i32,
+            height,
+            name: name.to_string(),
+            cells,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// Returns true if the (x, y) coordinate is within the arena's bounding rectangle
+    pub fn is_within_bounds(&self, (x, y): (i32, i32)) -> bool {
+        (0..self.width).contains(&x) && (0..self.height).contains(&y)
+    }
+
+    /// Cells that aren't walled off.
+    pub fn usable_cells(&self) -> usize {
+        self.cells.iter().filter(|&&terrain| terrain != Terrain::Wall).count()
+    }
+
+    /// Terrain at a cell. Everything outside the arena counts as wall.
+    pub fn terrain_at(&self, (x, y): (i32, i32)) -> Terrain {
+        if self.is_within_bounds((x, y)) {
+            self.cells[(y * self.width + x) as usize]
+        } else {
+            Terrain::Wall
+        }
+    }
+
+    pub fn elevation_at(&self, cell: (i32, i32)) -> i32 {
+        self.terrain_at(cell).elevation()
+    }
+
+    /// Cost of stepping from `from` into the neighbouring cell `to`, including
+    /// any climb, or `None` if the agent can't go there at all.
+    pub fn step_cost(&self, from: (i32, i32), to: (i32, i32), mobility: &Mobility) -> Option<i32> {
+        if !self.is_passable(to, mobility) {
+            return None;
         }
+        let climb = (self.elevation_at(to) - self.elevation_at(from)).max(0);
+        let move_cost = self.terrain_at(to).move_cost()?;
+        Some(move_cost + climb * mobility.climb_cost.unwrap_or(0))
+    }
+
+    /// Whether an agent could stand on `cell`. Raised cells need a climber.
+    pub fn is_passable(&self, cell: (i32, i32), mobility: &Mobility) -> bool {
+        let terrain = self.terrain_at(cell);
+        terrain.move_cost().is_some()
+            && !mobility.impassable.contains(&terrain)
+            && (terrain.elevation() == 0 || mobility.can_climb())
+    }
+
+    /// Ground-level cells an agent could start the battle on.
+    pub fn spawn_cells(&self, mobility: &Mobility) -> Vec<(i32, i32)> {
+        (0..self.width)
+            .flat_map(|x| (0..self.height).map(move |y| (x, y)))
+            .filter(|&cell| self.elevation_at(cell) == 0 && self.is_passable(cell, mobility))
+            .collect()
+    }
+
+    /// Closest cell within `radius` of `from` that is higher than `min_elevation`
+    /// and that the agent could climb.
+    pub fn nearest_high_cell(
+        &self,
+        from: (i32, i32),
+        radius: i32,
+        min_elevation: i32,
+        mobility: &Mobility,
+    ) -> Option<(i32, i32)> {
+        (-radius..=radius)
+            .flat_map(|dx| (-radius..=radius).map(move |dy| (from.0 + dx, from.1 + dy)))
+            .filter(|&cell| (cell.0 - from.0).abs() + (cell.1 - from.1).abs() <= radius)
+            .filter(|&cell| self.elevation_at(cell) > min_elevation && self.is_passable(cell, mobility))
+            .min_by_key(|&cell| ((cell.0 - from.0).abs() + (cell.1 - from.1).abs(), cell))
     }
 
-    pub fn random_position() -> (i32, i32) {
+    pub fn random_position(&self) -> (i32, i32) {
         let mut rng = rand::thread_rng();
-        (rng.gen_range(0..100), rng.gen_range(0..100))
+        (rng.gen_range(0..self.width), rng.gen_range(0..self.height))
     }
 
     pub fn update_positions(&mut self, agents: &mut [crate::models::Agent]) {
         // For now, this is a placeholder. We can implement more complex movement logic later.
+        let mut occupied = get_occupied_positions(agents);
+        let mut rng = rand::thread_rng();
         for agent in agents {
             if agent.alive {
-                // Simple random movement
-                let mut rng = rand::thread_rng();
+                // Simple random movement into a free cell
                 let dx = rng.gen_range(-1..=1);
                 let dy = rng.gen_range(-1..=1);
-                agent.x = (agent.x + dx).clamp(0, self.width - 1);
-                agent.y = (agent.y + dy).clamp(0, self.height - 1);
+                let next = ((agent.x + dx).clamp(0, self.width - 1), (agent.y + dy).clamp(0, self.height - 1));
+                if !occupied.contains(&next) && self.is_passable(next, &Mobility::default()) {
+                    occupied.remove(&(agent.x, agent.y));
+                    occupied.insert(next);
+                    agent.x = next.0;
+                    agent.y = next.1;
+                }
             }
         }
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_parse_with_or_without_commas() {
+        let arena = Arena::from_map("ford", "#.~\n\nm, *, ^\nT B .\n").unwrap();
+        assert_eq!((arena.name(), arena.width(), arena.height()), ("ford", 3, 3));
+        assert_eq!(arena.terrain_at((0, 0)), Terrain::Wall);
+        assert_eq!(arena.terrain_at((2, 0)), Terrain::Water);
+        assert_eq!(arena.terrain_at((0, 1)), Terrain::Mud);
+        assert_eq!(arena.terrain_at((2, 1)), Terrain::Rock);
+        assert_eq!(arena.terrain_at((1, 2)), Terrain::Boulder);
+        assert_eq!(arena.terrain_at((3, 0)), Terrain::Wall);
+        assert_eq!(arena.usable_cells(), 8);
+    }
+
+    fn map_error(text: &str) -> String {
+        let Err(error) = Arena::from_map("bad", text) else {
+            panic!("{:?} parsed", text);
+        };
+        error
+    }
+
+    #[test]
+    fn bad_maps_are_rejected() {
+        let ragged = map_error("...\n..\n");
+        assert!(ragged.contains("row 2 has 2 cells, expected 3"), "{}", ragged);
+        let unknown = map_error("..\n.?\n");
+        assert!(unknown.contains("unknown terrain '?' on row 2"), "{}", unknown);
+        assert_eq!(map_error("\n \n"), "map is empty");
+    }
+
+    #[test]
+    fn missing_map_files_are_reported() {
+        let Err(error) = Arena::load("maps/missing.txt") else {
+            panic!("a missing map loaded");
+        };
+        assert!(error.starts_with("could not open map maps/missing.txt"), "{}", error);
+    }
+}
@@ -1,13 +1,67 @@
 use serde::{Serialize, Deserialize};
+use crate::environment::AppliedModifiers;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CausalMetrics {
     pub total_critical_hits: u32,
     pub group_avg_damage: f32,
     pub max_group_damage: f32,
+    /// HP left of the first agent fighting with solo tactics.
     pub solo_end_hp: u32,
+    /// HP left across every agent fighting with solo tactics.
+    #[serde(default)]
+    pub solos_total_end_hp: u32,
     pub rounds_engaged: u32,
     pub solo_final_blow: bool,
+    #[serde(default)]
+    pub squads: u32,
+    #[serde(default)]
+    pub leaders_killed: u32,
+    #[serde(default)]
+    pub orders_issued: u32,
+    #[serde(default)]
+    pub group_sight_radius: u32,
+    #[serde(default)]
+    pub solo_sight_radius: u32,
+    #[serde(default)]
+    pub mutual_kills: u32,
+    #[serde(default)]
+    pub contested_moves: u32,
+    /// Times an agent slipped and lost its action, over every agent.
+    #[serde(default)]
+    pub slips: u32,
+    /// `slips` divided by the number of agents that fought.
+    #[serde(default)]
+    pub slips_per_agent: f32,
+    /// Environment modifiers in effect at the start and where they came from.
+    #[serde(default)]
+    pub environment: AppliedModifiers,
+    /// Environment modifiers in effect at the end, after any weather changes.
+    #[serde(default)]
+    pub final_environment: AppliedModifiers,
+    /// Sight radius of the first profile on each side in the final weather.
+    #[serde(default)]
+    pub group_final_sight_radius: u32,
+    #[serde(default)]
+    pub solo_final_sight_radius: u32,
+    /// Fatigue multipliers from the temperature for each side.
+    #[serde(default)]
+    pub group_thermal_strain: f32,
+    #[serde(default)]
+    pub solo_thermal_strain: f32,
+    /// Elevation the first solo agent finished on, 0 for the ground. Each
+    /// solo's is in `BattleResult::solos`.
+    #[serde(default)]
+    pub solo_end_elevation: u32,
+    /// Closest any two opponents stood at the start, in cells.
+    #[serde(default)]
+    pub start_separation: u32,
+}
+
+impl Default for CausalMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CausalMetrics {
@@ -17,8 +71,26 @@ impl CausalMetrics {
             group_avg_damage: 0.0,
             max_group_damage: 0.0,
             solo_end_hp: 0,
+            solos_total_end_hp: 0,
             rounds_engaged: 0,
             solo_final_blow: false,
+            squads: 0,
+            leaders_killed: 0,
+            orders_issued: 0,
+            group_sight_radius: 0,
+            solo_sight_radius: 0,
+            mutual_kills: 0,
+            contested_moves: 0,
+            slips: 0,
+            slips_per_agent: 0.0,
+            environment: AppliedModifiers::default(),
+            final_environment: AppliedModifiers::default(),
+            group_final_sight_radius: 0,
+            solo_final_sight_radius: 0,
+            group_thermal_strain: 1.0,
+            solo_thermal_strain: 1.0,
+            solo_end_elevation: 0,
+            start_separation: 0,
         }
     }
 } 
\ No newline at end of file
@@ -1,30 +1,899 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::File;
 use std::io::BufReader;
-use serde::Deserialize;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::solar::parse_date;
+use crate::terrain::Terrain;
 
-#[derive(Debug, Clone, Deserialize)]
+/// Profiles the simulation fights with.
+pub const PROFILES_FILE: &str = "combatants.json";
+
+/// Profiles built into the simulation, loaded before the profile file. Bump
+/// `version` in it whenever a profile changes.
+const LIBRARY: &str = include_str!("library.json");
+
+/// Lowest and highest value each stat can take. Draws from a distribution
+/// are clamped into range.
+const STAT_RANGES: [(&str, i32, i32); 14] = [
+    ("hp", 1, 5000),
+    ("str_", 0, 100),
+    ("spd", 1, 50),
+    ("def", 0, 100),
+    ("base_damage", 0, 200),
+    ("crit_chance", 0, 100),
+    ("endurance", 0, 30),
+    ("dexterity", 0, 30),
+    ("int_abstract", 0, 30),
+    ("int_environmental", 0, 30),
+    ("pain_tolerance", 0, 30),
+    ("heat_tolerance", 0, 20),
+    ("cold_tolerance", 0, 20),
+    ("climbing", 0, 20),
+];
+
+/// Behaviour flags the simulation knows. Others are allowed but warned about.
+pub const KNOWN_BEHAVIOR_FLAGS: [&str; 5] = ["aggressive", "cautious", "cooperative", "tactical", "territorial"];
+
+fn range_of(stat: &str) -> (i32, i32) {
+    STAT_RANGES
+        .iter()
+        .find(|(name, _, _)| *name == stat)
+        .map(|&(_, low, high)| (low, high))
+        .expect("every stat has a range")
+}
+
+/// Draws from a normal distribution outside `min`/`max` are retried this many
+/// times before being clamped into range.
+const TRUNCATION_RETRIES: u32 = 100;
+
+/// How a stat varies between individuals of a profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Distribution {
+    /// Bell curve around `mean`. Bounds, when given, truncate it.
+    Normal {
+        mean: f64,
+        sd: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max: Option<f64>,
+    },
+    /// Every value from `min` to `max` equally likely.
+    Uniform { min: f64, max: f64 },
+}
+
+impl Distribution {
+    fn validate(&self) -> Result<(), String> {
+        let (low, high) = match *self {
+            Distribution::Normal { mean, sd, min, max } => {
+                if !(mean.is_finite() && sd.is_finite() && sd >= 0.0) {
+                    return Err("normal needs a finite mean and an sd of at least 0".to_string());
+                }
+                (min.unwrap_or(f64::MIN), max.unwrap_or(f64::MAX))
+            }
+            Distribution::Uniform { min, max } => (min, max),
+        };
+        if !(low.is_finite() && high.is_finite()) || low > high {
+            return Err(format!("range {} to {} is empty", low, high));
+        }
+        Ok(())
+    }
+
+    fn mean(&self) -> f64 {
+        match *self {
+            Distribution::Normal { mean, min, max, .. } => {
+                mean.clamp(min.unwrap_or(f64::MIN), max.unwrap_or(f64::MAX))
+            }
+            Distribution::Uniform { min, max } => (min + max) / 2.0,
+        }
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> f64 {
+        match *self {
+            Distribution::Normal { mean, sd, min, max } => {
+                let (low, high) = (min.unwrap_or(f64::MIN), max.unwrap_or(f64::MAX));
+                let mut value = mean;
+                for _ in 0..TRUNCATION_RETRIES {
+                    value = mean + sd * standard_normal(rng);
+                    if (low..=high).contains(&value) {
+                        return value;
+                    }
+                }
+                value.clamp(low, high)
+            }
+            Distribution::Uniform { min, max } => rng.gen_range(min..=max),
+        }
+    }
+
+    fn shifted(&self, delta: f64) -> Distribution {
+        match *self {
+            Distribution::Normal { mean, sd, min, max } => Distribution::Normal {
+                mean: mean + delta,
+                sd,
+                min: min.map(|min| min + delta),
+                max: max.map(|max| max + delta),
+            },
+            Distribution::Uniform { min, max } => Distribution::Uniform {
+                min: min + delta,
+                max: max + delta,
+            },
+        }
+    }
+}
+
+/// Box-Muller transform.
+fn standard_normal(rng: &mut impl Rng) -> f64 {
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// Measurements of a typical individual, in kg, cm, N and m/s. Stats a
+/// profile doesn't set are derived from them through `CONVERSIONS`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Physical {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mass_kg: Option<f64>,
+    /// Standing height, or head-and-body length for animals that stay on all fours.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height_cm: Option<f64>,
+    /// Maximal grip force of one hand.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grip_n: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub punch_n: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bite_n: Option<f64>,
+    /// Top speed over a short burst.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sprint_mps: Option<f64>,
+}
+
+/// How one stat follows from one measure: a power law through two anchor
+/// points, `(measure, stat)` for the average man and the mature male lowland
+/// gorilla of research/Human-vs-Gorilla-Characteristics.md. The anchors are
+/// the stats Man and Gorilla were given by hand, so measuring either gives
+/// them back, and other bodies land on the same curve.
+struct Conversion {
+    stat: &'static str,
+    measure: &'static str,
+    man: (f64, f64),
+    gorilla: (f64, f64),
+}
+
+impl Conversion {
+    fn apply(&self, measure: f64) -> i32 {
+        let ((man_measure, man_stat), (gorilla_measure, gorilla_stat)) = (self.man, self.gorilla);
+        let exponent = (gorilla_stat / man_stat).ln() / (gorilla_measure / man_measure).ln();
+        let (low, high) = range_of(self.stat);
+        ((man_stat * (measure / man_measure).powf(exponent)).round() as i32).clamp(low, high)
+    }
+}
+
+/// The conversion model. Anchors come from the research summary table:
+/// - hp from body mass: 72 kg and 170 kg.
+/// - str_ from grip: 550 N and 1780 N.
+/// - spd from sprint speed: 7.5 m/s (27 km/h) and 11.1 m/s (40 km/h).
+/// - def from build, mass per cm of height: 72 kg over 171 cm and 170 kg over 170 cm.
+/// - base_damage from the harder of punch and bite: an untrained man's punch
+///   of about 2500 N, which the research leaves open, and the gorilla's 8960 N bite.
+const CONVERSIONS: [Conversion; 5] = [
+    Conversion { stat: "hp", measure: "mass_kg", man: (72.0, 100.0), gorilla: (170.0, 300.0) },
+    Conversion { stat: "str_", measure: "grip_n", man: (550.0, 10.0), gorilla: (1780.0, 40.0) },
+    Conversion { stat: "spd", measure: "sprint_mps", man: (7.5, 10.0), gorilla: (11.1, 12.0) },
+    Conversion { stat: "def", measure: "mass_kg / height_cm", man: (72.0 / 171.0, 8.0), gorilla: (1.0, 20.0) },
+    Conversion { stat: "base_damage", measure: "max(punch_n, bite_n)", man: (2500.0, 5.0), gorilla: (8960.0, 20.0) },
+];
+
+impl Physical {
+    fn validate(&self) -> Result<(), String> {
+        let values = [
+            ("mass_kg", self.mass_kg),
+            ("height_cm", self.height_cm),
+            ("grip_n", self.grip_n),
+            ("punch_n", self.punch_n),
+            ("bite_n", self.bite_n),
+            ("sprint_mps", self.sprint_mps),
+        ];
+        match values.iter().find(|(_, value)| value.is_some_and(|v| !(v > 0.0 && v.is_finite()))) {
+            Some((name, _)) => Err(format!("physical: {} must be a positive number", name)),
+            None => Ok(()),
+        }
+    }
+
+    /// The value of one of the measures in `CONVERSIONS`, if it was measured.
+    fn measure(&self, measure: &str) -> Option<f64> {
+        match measure {
+            "mass_kg" => self.mass_kg,
+            "grip_n" => self.grip_n,
+            "sprint_mps" => self.sprint_mps,
+            "mass_kg / height_cm" => Some(self.mass_kg? / self.height_cm?),
+            "max(punch_n, bite_n)" => match (self.punch_n, self.bite_n) {
+                (Some(punch), Some(bite)) => Some(punch.max(bite)),
+                (punch, bite) => punch.or(bite),
+            },
+            _ => unreachable!("unknown measure {}", measure),
+        }
+    }
+
+    /// `stat` as derived from these measurements, if they include its measure.
+    pub fn derive(&self, stat: &str) -> Option<i32> {
+        let conversion = CONVERSIONS.iter().find(|c| c.stat == stat)?;
+        Some(conversion.apply(self.measure(conversion.measure)?))
+    }
+
+    /// How `stat` was derived, e.g. "hp 100 from mass_kg 72".
+    pub fn explain(&self, stat: &str) -> Option<String> {
+        let conversion = CONVERSIONS.iter().find(|c| c.stat == stat)?;
+        let measure = self.measure(conversion.measure)?;
+        let shown = (measure * 1000.0).round() / 1000.0;
+        Some(format!("{} {} from {} {}", stat, conversion.apply(measure), conversion.measure, shown))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl std::fmt::Display for Confidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// Where a profile's numbers come from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Provenance {
+    pub source: String,
+    pub confidence: Confidence,
+    /// When the numbers were last checked, as "YYYY-MM-DD".
+    pub date: String,
+}
+
+impl Provenance {
+    fn validate(&self) -> Result<(), String> {
+        if self.source.trim().is_empty() {
+            return Err("provenance: source is empty".to_string());
+        }
+        // parse_date checks the month and day but ignores the year
+        let parts: Vec<&str> = self.date.split('-').collect();
+        let digits = |part: &str, len: usize| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+        let [year, month, day] = parts.as_slice() else {
+            return Err(format!("provenance: date '{}' is not YYYY-MM-DD", self.date));
+        };
+        if !digits(year, 4) || !digits(month, 2) || !digits(day, 2) {
+            return Err(format!("provenance: date '{}' is not YYYY-MM-DD", self.date));
+        }
+        parse_date(&self.date).map_err(|e| format!("provenance: {}", e))?;
+        let year: u32 = year.parse().expect("checked to be digits");
+        let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+        if *month == "02" && *day == "29" && !leap {
+            return Err(format!("provenance: {} is not a leap year in date '{}'", year, self.date));
+        }
+        Ok(())
+    }
+}
+
+/// A profile stat: the same number for everyone, or a distribution each
+/// individual draws from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Stat {
+    Fixed(i32),
+    Varies(Distribution),
+}
+
+impl Stat {
+    /// The fixed value, or the middle of the distribution rounded.
+    pub fn value(&self) -> i32 {
+        match self {
+            Stat::Fixed(value) => *value,
+            Stat::Varies(distribution) => distribution.mean().round() as i32,
+        }
+    }
+
+    /// One individual's value, kept within `low` to `high`.
+    pub fn draw(&self, rng: &mut impl Rng, (low, high): (i32, i32)) -> i32 {
+        match self {
+            Stat::Fixed(value) => *value,
+            Stat::Varies(distribution) => (distribution.sample(rng).round() as i32).clamp(low, high),
+        }
+    }
+
+    /// The stat moved by `delta`. A distribution moves as a whole.
+    pub fn shifted(&self, delta: f64) -> Stat {
+        match self {
+            Stat::Fixed(value) => Stat::Fixed((*value as f64 + delta).round() as i32),
+            Stat::Varies(distribution) => Stat::Varies(distribution.shifted(delta)),
+        }
+    }
+
+    /// The value, or a distribution's middle and bounds, must lie within
+    /// `low` to `high`. Unbounded tails are clamped when drawn.
+    fn check_range(&self, (low, high): (i32, i32)) -> Result<(), String> {
+        let points = match *self {
+            Stat::Fixed(value) => vec![value as f64],
+            Stat::Varies(Distribution::Normal { mean, min, max, .. }) => [Some(mean), min, max].into_iter().flatten().collect(),
+            Stat::Varies(Distribution::Uniform { min, max }) => vec![min, max],
+        };
+        match points.into_iter().find(|p| !(low as f64..=high as f64).contains(p)) {
+            Some(p) => Err(format!("{} is outside {} to {}", p, low, high)),
+            None => Ok(()),
+        }
+    }
+
+    /// Short form for listings: the value, marked with ~ if it varies.
+    pub fn summary(&self) -> String {
+        match self {
+            Stat::Fixed(value) => value.to_string(),
+            Stat::Varies(_) => format!("~{}", self.value()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AgentProfile {
-    pub hp: i32,
-    pub str_: i32,
-    pub spd: i32,
-    pub def: i32,
-    pub base_damage: i32,
-    pub crit_chance: i32,
-    pub endurance: i32,
-    pub dexterity: i32,
-    pub int_abstract: i32,
-    pub int_environmental: i32,
-    pub pain_tolerance: i32,
+    pub hp: Stat,
+    pub str_: Stat,
+    pub spd: Stat,
+    pub def: Stat,
+    pub base_damage: Stat,
+    pub crit_chance: Stat,
+    pub endurance: Stat,
+    pub dexterity: Stat,
+    pub int_abstract: Stat,
+    pub int_environmental: Stat,
+    pub pain_tolerance: Stat,
+    /// Resistance to heat and cold stress, 0-20. 10 when not given.
+    #[serde(default = "default_tolerance")]
+    pub heat_tolerance: Stat,
+    #[serde(default = "default_tolerance")]
+    pub cold_tolerance: Stat,
+    /// Terrain this combatant can't enter. Walls are impassable to everyone.
+    #[serde(default)]
+    pub impassable_terrain: Vec<Terrain>,
+    /// Climbing skill, 0-20. 0 means it can't climb trees or boulders.
+    #[serde(default = "default_climbing")]
+    pub climbing: Stat,
     pub behavior_flags: Vec<String>,
+    /// Measurements that stats left out are derived from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub physical: Option<Physical>,
+    /// Other names the profile can be looked up by, such as "silverback".
+    /// Not inherited by profiles that extend this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Where the numbers come from. Not inherited by profiles that extend this one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// Stats that were derived from `physical` rather than given.
+    #[serde(skip)]
+    pub derived: Vec<String>,
+}
+
+fn default_tolerance() -> Stat {
+    Stat::Fixed(10)
 }
 
-pub fn load_profiles(path: &str) -> HashMap<String, AgentProfile> {
-    let file = File::open(path).expect("Could not open profile file");
-    let reader = BufReader::new(file);
-    serde_json::from_reader(reader).expect("Failed to deserialize profiles")
+fn default_climbing() -> Stat {
+    Stat::Fixed(0)
 }
 
-pub fn get_profile<'a>(profiles: &'a HashMap<String, AgentProfile>, name: &str) -> &'a AgentProfile {
-    profiles.get(name).expect("Profile not found")
-} 
\ No newline at end of file
+impl AgentProfile {
+    /// Every stat with its name, in the order of `STAT_RANGES`.
+    fn stats(&self) -> [(&'static str, &Stat); 14] {
+        [
+            ("hp", &self.hp),
+            ("str_", &self.str_),
+            ("spd", &self.spd),
+            ("def", &self.def),
+            ("base_damage", &self.base_damage),
+            ("crit_chance", &self.crit_chance),
+            ("endurance", &self.endurance),
+            ("dexterity", &self.dexterity),
+            ("int_abstract", &self.int_abstract),
+            ("int_environmental", &self.int_environmental),
+            ("pain_tolerance", &self.pain_tolerance),
+            ("heat_tolerance", &self.heat_tolerance),
+            ("cold_tolerance", &self.cold_tolerance),
+            ("climbing", &self.climbing),
+        ]
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(physical) = &self.physical {
+            physical.validate()?;
+        }
+        if let Some(provenance) = &self.provenance {
+            provenance.validate()?;
+        }
+        for (name, stat) in self.stats() {
+            if let Stat::Varies(distribution) = stat {
+                distribution.validate().map_err(|e| format!("{}: {}", name, e))?;
+            }
+            stat.check_range(range_of(name)).map_err(|e| format!("{}: {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    /// Behaviour flags outside `KNOWN_BEHAVIOR_FLAGS`.
+    pub fn unknown_flags(&self) -> Vec<&str> {
+        self.behavior_flags
+            .iter()
+            .map(String::as_str)
+            .filter(|flag| !KNOWN_BEHAVIOR_FLAGS.contains(flag))
+            .collect()
+    }
+
+    /// One individual of this profile, with every stat drawn and fixed.
+    /// Stats are drawn in field order, so the same `rng` state always gives
+    /// the same individual.
+    pub fn individual(&self, rng: &mut impl Rng) -> AgentProfile {
+        let mut draw = |name: &str, stat: &Stat| Stat::Fixed(stat.draw(rng, range_of(name)));
+        AgentProfile {
+            hp: draw("hp", &self.hp),
+            str_: draw("str_", &self.str_),
+            spd: draw("spd", &self.spd),
+            def: draw("def", &self.def),
+            base_damage: draw("base_damage", &self.base_damage),
+            crit_chance: draw("crit_chance", &self.crit_chance),
+            endurance: draw("endurance", &self.endurance),
+            dexterity: draw("dexterity", &self.dexterity),
+            int_abstract: draw("int_abstract", &self.int_abstract),
+            int_environmental: draw("int_environmental", &self.int_environmental),
+            pain_tolerance: draw("pain_tolerance", &self.pain_tolerance),
+            heat_tolerance: draw("heat_tolerance", &self.heat_tolerance),
+            cold_tolerance: draw("cold_tolerance", &self.cold_tolerance),
+            impassable_terrain: self.impassable_terrain.clone(),
+            climbing: draw("climbing", &self.climbing),
+            behavior_flags: self.behavior_flags.clone(),
+            physical: self.physical.clone(),
+            aliases: self.aliases.clone(),
+            provenance: self.provenance.clone(),
+            derived: self.derived.clone(),
+        }
+    }
+
+    /// How each derived stat follows from the measurements.
+    pub fn derivations(&self) -> Vec<String> {
+        let Some(physical) = &self.physical else {
+            return Vec::new();
+        };
+        self.derived.iter().filter_map(|stat| physical.explain(stat)).collect()
+    }
+
+    /// One line of the main stats for listings.
+    pub fn summary(&self) -> String {
+        self.stats()[..6]
+            .iter()
+            .map(|(name, stat)| format!("{} {}", name, stat.summary()))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+/// The built-in library and the profiles in a profile file, resolved and
+/// checked. The one place combatant stats come from.
+#[derive(Debug, Clone)]
+pub struct ProfileRegistry {
+    /// File the profiles were read from.
+    pub path: String,
+    /// Version of the built-in library.
+    pub library_version: u32,
+    profiles: BTreeMap<String, AgentProfile>,
+    /// The profile each profile extends, if any.
+    parents: BTreeMap<String, String>,
+    /// Profiles that come from the library rather than the file.
+    built_in: BTreeSet<String>,
+    /// Every name and alias in lowercase, with the profile it stands for.
+    index: BTreeMap<String, String>,
+}
+
+/// The built-in library file.
+#[derive(Deserialize)]
+struct Library {
+    version: u32,
+    profiles: Map<String, Value>,
+}
+
+impl ProfileRegistry {
+    /// Reads the profiles, printing any warnings.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let registry = Self::read(path).map_err(|e| format!("Invalid profiles in {}: {}", path, e))?;
+        for warning in registry.warnings() {
+            eprintln!("Warning: {}: {}", path, warning);
+        }
+        Ok(registry)
+    }
+
+    /// Reads and resolves the profiles in `path` alongside the built-in
+    /// library. A profile in the file replaces a library one of the same name,
+    /// whatever its case.
+    /// A profile can set `extends` to build on another: its fields replace the
+    /// parent's, and a stat given as a string such as `"+3"` or `"-2.5"` moves
+    /// the parent's.
+    pub fn read(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("could not open profile file: {}", e))?;
+        let reader = BufReader::new(file);
+        let raw: Map<String, Value> =
+            serde_json::from_reader(reader).map_err(|e| format!("failed to deserialize profiles: {}", e))?;
+        Self::resolve(path, &raw)
+    }
+
+    pub fn resolve(path: &str, raw: &Map<String, Value>) -> Result<Self, String> {
+        let library: Library = serde_json::from_str(LIBRARY).expect("Failed to deserialize the profile library");
+        let replaced: BTreeSet<String> = raw.keys().map(|name| name.to_lowercase()).collect();
+        let mut all = library.profiles;
+        all.retain(|name, _| !replaced.contains(&name.to_lowercase()));
+        let built_in = all.keys().cloned().collect();
+        all.extend(raw.clone());
+
+        let lookup = raw_index(&all);
+        let mut profiles = BTreeMap::new();
+        for name in all.keys() {
+            resolve(name, &all, &lookup, &mut profiles, &mut Vec::new())?;
+        }
+        let parents = all
+            .iter()
+            .filter_map(|(name, fields)| {
+                let parent = fields.get("extends")?.as_str()?;
+                Some((name.clone(), lookup.get(&parent.to_lowercase())?.clone()))
+            })
+            .collect();
+        let index = index(&profiles)?;
+        Ok(ProfileRegistry {
+            path: path.to_string(),
+            library_version: library.version,
+            profiles,
+            parents,
+            built_in,
+            index,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Result<&AgentProfile, String> {
+        self.find(name).map(|(_, profile)| profile)
+    }
+
+    /// Looks `name` up ignoring case, among profile names and their aliases,
+    /// and returns the profile's own name with it. The error suggests close
+    /// matches.
+    pub fn find(&self, name: &str) -> Result<(&str, &AgentProfile), String> {
+        let found = self.index.get(&name.to_lowercase()).and_then(|name| self.profiles.get_key_value(name));
+        let Some((name, profile)) = found else {
+            let mut error = format!("no profile named {} in {} or the built-in library", name, self.path);
+            let candidates = self.index.iter().map(|(key, name)| (key.as_str(), name.as_str()));
+            if let Some(suggestion) = suggest(name, candidates) {
+                error.push_str(&format!(". Did you mean {}?", suggestion));
+            }
+            return Err(error);
+        };
+        Ok((name, profile))
+    }
+
+    /// Profiles by name, in alphabetical order.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &AgentProfile)> {
+        self.profiles.iter()
+    }
+
+    pub fn parent(&self, name: &str) -> Option<&str> {
+        self.parents.get(name).map(String::as_str)
+    }
+
+    /// Whether `name` comes from the built-in library.
+    pub fn is_built_in(&self, name: &str) -> bool {
+        self.built_in.contains(name)
+    }
+
+    /// Problems that don't stop the profiles being used.
+    pub fn warnings(&self) -> Vec<String> {
+        self.profiles
+            .iter()
+            .flat_map(|(name, profile)| {
+                profile
+                    .unknown_flags()
+                    .into_iter()
+                    .map(move |flag| format!("{} has unknown behavior flag {}", name, flag))
+            })
+            .collect()
+    }
+}
+
+/// Maps every name and alias in `raw`, in lowercase, to its profile, so
+/// `extends` finds parents the way `ProfileRegistry::find` does. A name wins
+/// over an alias; clashes are reported once the profiles are resolved.
+fn raw_index(raw: &Map<String, Value>) -> BTreeMap<String, String> {
+    let mut lookup: BTreeMap<String, String> =
+        raw.keys().map(|name| (name.to_lowercase(), name.clone())).collect();
+    for (name, fields) in raw {
+        let aliases = fields.get("aliases").and_then(Value::as_array).into_iter().flatten();
+        for alias in aliases.filter_map(Value::as_str) {
+            lookup.entry(alias.to_lowercase()).or_insert_with(|| name.clone());
+        }
+    }
+    lookup
+}
+
+/// Resolves `name` and every profile it extends. `lookup` finds parents by
+/// name or alias, ignoring case. `chain` holds the profiles being resolved
+/// further down, to catch a profile extending itself.
+fn resolve(
+    name: &str,
+    raw: &Map<String, Value>,
+    lookup: &BTreeMap<String, String>,
+    resolved: &mut BTreeMap<String, AgentProfile>,
+    chain: &mut Vec<String>,
+) -> Result<AgentProfile, String> {
+    if let Some(profile) = resolved.get(name) {
+        return Ok(profile.clone());
+    }
+    if chain.iter().any(|n| n == name) {
+        chain.push(name.to_string());
+        return Err(format!("profiles extend each other in a loop: {}", chain.join(" -> ")));
+    }
+    let Some(fields) = raw.get(name).and_then(Value::as_object) else {
+        return Err(match chain.last() {
+            Some(child) => {
+                let candidates = lookup.iter().map(|(key, name)| (key.as_str(), name.as_str()));
+                match suggest(name, candidates) {
+                    Some(suggestion) => format!("{} extends unknown profile {}. Did you mean {}?", child, name, suggestion),
+                    None => format!("{} extends unknown profile {}", child, name),
+                }
+            }
+            None => format!("profile {} is not an object", name),
+        });
+    };
+
+    // Stats the parent derived are derived again, from the measurements as
+    // this profile changes them
+    let mut merged = match fields.get("extends") {
+        Some(parent) => {
+            let parent = parent.as_str().ok_or(format!("{}: extends must be a profile name", name))?;
+            let parent = lookup.get(&parent.to_lowercase()).map_or(parent, String::as_str);
+            chain.push(name.to_string());
+            let parent = resolve(parent, raw, lookup, resolved, chain)?;
+            chain.pop();
+            match serde_json::to_value(&parent) {
+                Ok(Value::Object(mut fields)) => {
+                    fields.remove("aliases");
+                    fields.remove("provenance");
+                    for stat in &parent.derived {
+                        fields.remove(stat);
+                    }
+                    fields
+                }
+                _ => unreachable!("profiles serialize to objects"),
+            }
+        }
+        None => Map::new(),
+    };
+    let mut deltas = Vec::new();
+    for (field, value) in fields.iter().filter(|(field, _)| *field != "extends") {
+        if let Some(delta) = value.as_str().and_then(parse_delta) {
+            deltas.push((field, delta));
+        } else if let (true, Some(Value::Object(inherited)), Value::Object(given)) =
+            (field == "physical", merged.get_mut(field), value)
+        {
+            // Measurements are inherited one by one
+            inherited.extend(given.clone());
+        } else {
+            merged.insert(field.clone(), value.clone());
+        }
+    }
+    let mut derived = derive(&mut merged).map_err(|e| format!("{}: {}", name, e))?;
+    for (field, delta) in deltas {
+        let stat: Stat = merged
+            .get(field)
+            .and_then(|stat| serde_json::from_value(stat.clone()).ok())
+            .ok_or(format!("{}: {} is relative but there is no inherited {} to change", name, field, field))?;
+        merged.insert(field.clone(), serde_json::to_value(stat.shifted(delta)).expect("stats serialize"));
+        derived.retain(|stat| stat != field);
+    }
+
+    let mut profile: AgentProfile =
+        serde_json::from_value(Value::Object(merged)).map_err(|e| format!("{}: {}", name, e))?;
+    profile.derived = derived;
+    profile.validate().map_err(|e| format!("{}: {}", name, e))?;
+    resolved.insert(name.to_string(), profile.clone());
+    Ok(profile)
+}
+
+/// Fills in the stats `fields` leave out that its physical measurements give,
+/// and returns their names.
+fn derive(fields: &mut Map<String, Value>) -> Result<Vec<String>, String> {
+    let Some(physical) = fields.get("physical") else {
+        return Ok(Vec::new());
+    };
+    let physical: Physical = serde_json::from_value(physical.clone()).map_err(|e| format!("physical: {}", e))?;
+    physical.validate()?;
+    let mut derived = Vec::new();
+    for conversion in &CONVERSIONS {
+        if fields.contains_key(conversion.stat) {
+            continue;
+        }
+        if let Some(value) = physical.derive(conversion.stat) {
+            fields.insert(conversion.stat.to_string(), Value::from(value));
+            derived.push(conversion.stat.to_string());
+        }
+    }
+    Ok(derived)
+}
+
+/// Maps every profile name and alias, in lowercase, to its profile. Names
+/// and aliases must all differ, ignoring case.
+fn index(profiles: &BTreeMap<String, AgentProfile>) -> Result<BTreeMap<String, String>, String> {
+    let mut index: BTreeMap<String, String> = BTreeMap::new();
+    let names = profiles.keys().map(|name| (name, name));
+    let aliases = profiles.iter().flat_map(|(name, profile)| profile.aliases.iter().map(move |alias| (alias, name)));
+    for (key, name) in names.chain(aliases) {
+        match index.insert(key.to_lowercase(), name.clone()) {
+            Some(other) if other != *name && key == name => {
+                return Err(format!("profiles {} and {} have the same name apart from case", other, name));
+            }
+            Some(other) if other != *name => {
+                return Err(format!("{}: alias {} is already a name for {}", name, key, other));
+            }
+            _ => {}
+        }
+    }
+    Ok(index)
+}
+
+/// Most edits a typo of a name this long can have and still be suggested.
+fn typo_limit(name: &str) -> usize {
+    (name.chars().count() / 3).max(1)
+}
+
+/// The profiles to suggest for an unknown `name`, such as "Gorilla" or
+/// "Man or Wolf": those whose key (a name or alias) is the fewest edits away
+/// within `typo_limit`, or else those whose key contains it as a word.
+fn suggest<'a>(name: &str, candidates: impl Iterator<Item = (&'a str, &'a str)>) -> Option<String> {
+    let name = name.to_lowercase();
+    let candidates: Vec<(usize, &str, &str)> =
+        candidates.map(|(key, profile)| (edit_distance(&name, &key.to_lowercase()), key, profile)).collect();
+    let closest = candidates.iter().map(|&(distance, _, _)| distance).min()?;
+    let mut matches: Vec<&str> = if closest <= typo_limit(&name) {
+        candidates.iter().filter(|&&(distance, _, _)| distance == closest).map(|&(_, _, profile)| profile).collect()
+    } else {
+        candidates
+            .iter()
+            .filter(|(_, key, _)| key.to_lowercase().split_whitespace().any(|word| word == name))
+            .map(|&(_, _, profile)| profile)
+            .collect()
+    };
+    matches.sort_unstable();
+    matches.dedup();
+    (!matches.is_empty()).then(|| matches.join(" or "))
+}
+
+/// Levenshtein distance: the fewest single-character insertions, deletions
+/// and substitutions that turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// A relative change such as "+3" or "-2.5".
+fn parse_delta(text: &str) -> Option<f64> {
+    if !text.starts_with(['+', '-']) {
+        return None;
+    }
+    text.parse().ok().filter(|delta: &f64| delta.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(raw: Value) -> Result<ProfileRegistry, String> {
+        ProfileRegistry::resolve("test.json", raw.as_object().expect("test profiles are an object"))
+    }
+
+    #[test]
+    fn extends_applies_deltas_after_the_parent() {
+        let profiles = registry(serde_json::json!({
+            "Tired Man": { "extends": "Untrained Man", "hp": "-20", "endurance": "-4", "dexterity": 8 },
+            "Heavy Man": { "extends": "Untrained Man", "physical": { "mass_kg": 110 } }
+        }))
+        .unwrap();
+        let parent = profiles.get("Untrained Man").unwrap();
+        let tired = profiles.get("Tired Man").unwrap();
+        assert_eq!(tired.hp.value(), parent.hp.value() - 20);
+        assert_eq!(tired.endurance.value(), parent.endurance.value() - 4);
+        assert_eq!(tired.dexterity.value(), 8);
+        assert_eq!(tired.str_, parent.str_);
+        assert_eq!(profiles.parent("Tired Man"), Some("Untrained Man"));
+
+        // Only the mass changes, and hp is derived again from it
+        let heavy = profiles.get("Heavy Man").unwrap();
+        let physical = heavy.physical.as_ref().unwrap();
+        assert_eq!(physical.mass_kg, Some(110.0));
+        assert_eq!(physical.grip_n, parent.physical.as_ref().unwrap().grip_n);
+        assert!(heavy.hp.value() > parent.hp.value());
+    }
+
+    #[test]
+    fn extends_in_a_loop_is_an_error() {
+        let Err(error) = registry(serde_json::json!({
+            "A": { "extends": "B", "hp": "+1" },
+            "B": { "extends": "A", "hp": "+1" }
+        })) else {
+            panic!("a loop must not resolve");
+        };
+        assert!(error.contains("loop"), "{}", error);
+        assert!(error.contains("A -> B -> A") || error.contains("B -> A -> B"), "{}", error);
+    }
+
+    fn apes() -> ProfileRegistry {
+        registry(serde_json::json!({
+            "Gorilla": { "extends": "Chimpanzee", "aliases": ["silverback", "western lowland gorilla"] }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn finds_profiles_ignoring_case_and_by_alias() {
+        let profiles = apes();
+        assert_eq!(profiles.find("gorilla").unwrap().0, "Gorilla");
+        assert_eq!(profiles.find("Silverback").unwrap().0, "Gorilla");
+        assert_eq!(profiles.find("chimp").unwrap().0, "Chimpanzee");
+    }
+
+    #[test]
+    fn suggests_close_names_and_aliases() {
+        let profiles = apes();
+        let error = profiles.find("gorila").unwrap_err();
+        assert!(error.ends_with("Did you mean Gorilla?"), "{}", error);
+        let error = profiles.find("silverbak").unwrap_err();
+        assert!(error.ends_with("Did you mean Gorilla?"), "{}", error);
+        // A whole word of a longer name
+        let error = profiles.find("lowland").unwrap_err();
+        assert!(error.ends_with("Did you mean Gorilla?"), "{}", error);
+        assert!(!profiles.find("zebra").unwrap_err().contains("Did you mean"));
+    }
+
+    #[test]
+    fn edit_distance_counts_single_character_edits() {
+        assert_eq!(edit_distance("gorila", "gorilla"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "man"), 3);
+    }
+
+    fn dated(date: &str) -> Provenance {
+        Provenance { source: "test".to_string(), confidence: Confidence::Low, date: date.to_string() }
+    }
+
+    #[test]
+    fn provenance_needs_a_real_date() {
+        assert!(dated("2026-10-19").validate().is_ok());
+        assert!(dated("2024-02-29").validate().is_ok());
+        for date in ["2024-13-40", "2024-04-31", "2023-02-29", "1900-02-29", "24-01-01", "abcd-01-01", "2024-1-01", "01-01"] {
+            assert!(dated(date).validate().is_err(), "{} passed", date);
+        }
+    }
+}
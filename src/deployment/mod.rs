@@ -0,0 +1,379 @@
+use crate::arena::Arena;
+use crate::terrain::Mobility;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::f64::consts::PI;
+
+/// Starting cells for one side.
+pub type Positions = Vec<(i32, i32)>;
+
+/// How a side is arranged at the start of a battle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Formation {
+    /// Spread at random over the spawn zone.
+    #[default]
+    Scattered,
+    /// Packed tightly around a random spot in the spawn zone.
+    Clustered,
+    /// Ranks centred on the spawn zone, facing the enemy.
+    Line,
+    /// A wedge centred on the spawn zone with its point towards the enemy.
+    Wedge,
+    /// Surrounding the enemy at `min_separation` or wider. Only one team can form a ring.
+    Ring,
+}
+
+/// Rectangle a side spawns in, as fractions of the arena's width and height.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpawnZone {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Default for SpawnZone {
+    fn default() -> Self {
+        SpawnZone {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+        }
+    }
+}
+
+impl SpawnZone {
+    pub fn validate(&self) -> Result<(), String> {
+        let within = |start: f64, size: f64| start >= 0.0 && size > 0.0 && start + size <= 1.0 + 1e-9;
+        if !within(self.x, self.width) || !within(self.y, self.height) {
+            return Err(format!("spawn zone {:?} must lie within the arena (fractions from 0 to 1)", self));
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, arena: &Arena, (x, y): (i32, i32)) -> bool {
+        let (width, height) = (arena.width() as f64, arena.height() as f64);
+        let (x, y) = (x as f64 + 0.5, y as f64 + 0.5);
+        (self.x * width..=(self.x + self.width) * width).contains(&x)
+            && (self.y * height..=(self.y + self.height) * height).contains(&y)
+    }
+
+    fn centre(&self, arena: &Arena) -> (f64, f64) {
+        (
+            (self.x + self.width / 2.0) * arena.width() as f64,
+            (self.y + self.height / 2.0) * arena.height() as f64,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SideDeployment {
+    /// The whole arena if unset.
+    pub zone: SpawnZone,
+    pub formation: Formation,
+}
+
+/// One team to place: its zone and formation, what it can stand on, how
+/// many there are and which alliance it fights for.
+pub struct Side<'a> {
+    pub deployment: &'a SideDeployment,
+    pub mobility: &'a Mobility,
+    pub count: usize,
+    pub alliance: usize,
+}
+
+/// Where and how the teams start the battle.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Deployment {
+    /// Zone and formation of the "Group" team when a scenario doesn't list its teams.
+    pub group: SideDeployment,
+    /// Zone and formation of the "Solo" team when a scenario doesn't list its teams.
+    pub solo: SideDeployment,
+    /// Fewest cells (Manhattan distance) between any two opponents at the start.
+    pub min_separation: i32,
+    /// Both sides start out knowing where every enemy stands, as in a charge
+    /// across open ground. Otherwise they only know what they can see.
+    pub start_aware: bool,
+}
+
+impl Deployment {
+    /// Zones and formations are checked with the teams they belong to.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_separation < 0 {
+            return Err(format!("min_separation can't be negative, got {}", self.min_separation));
+        }
+        Ok(())
+    }
+
+    /// Ground cells in the side's spawn zone that an agent with `mobility` could start on.
+    pub fn zone_cells(&self, arena: &Arena, side: &SideDeployment, mobility: &Mobility) -> Vec<(i32, i32)> {
+        arena
+            .spawn_cells(mobility)
+            .into_iter()
+            .filter(|&cell| side.zone.contains(arena, cell))
+            .collect()
+    }
+
+    /// Starting cells for each side, in the order given. Smaller sides are
+    /// placed first, as they have the fewest cells to choose from, and a side
+    /// forming a ring goes last so it can surround the enemies already placed.
+    pub fn place(&self, arena: &Arena, sides: &[Side], rng: &mut impl Rng) -> Result<Vec<Positions>, String> {
+        let mut order: Vec<usize> = (0..sides.len()).collect();
+        order.sort_by_key(|&i| (sides[i].deployment.formation == Formation::Ring, sides[i].count));
+
+        let mut placed: Vec<Option<Positions>> = vec![None; sides.len()];
+        for i in order {
+            let cells = self.place_side(arena, sides, i, &placed, rng)?;
+            placed[i] = Some(cells);
+        }
+        Ok(placed.into_iter().map(|cells| cells.expect("every side is placed")).collect())
+    }
+
+    /// Picks cells for side `i`. Enemies already placed are kept at
+    /// `min_separation`; the formation faces their centre, or the centre of
+    /// the enemies' zones if none are placed yet.
+    fn place_side(
+        &self,
+        arena: &Arena,
+        sides: &[Side],
+        i: usize,
+        placed: &[Option<Positions>],
+        rng: &mut impl Rng,
+    ) -> Result<Positions, String> {
+        let side = &sides[i];
+        let taken: HashSet<&(i32, i32)> = placed.iter().flatten().flatten().collect();
+        let enemies: Vec<(i32, i32)> = placed
+            .iter()
+            .zip(sides)
+            .filter(|(_, other)| other.alliance != side.alliance)
+            .filter_map(|(cells, _)| cells.as_ref())
+            .flatten()
+            .copied()
+            .collect();
+        let candidates: Vec<(i32, i32)> = self
+            .zone_cells(arena, side.deployment, side.mobility)
+            .into_iter()
+            .filter(|cell| !taken.contains(cell))
+            .filter(|&(x, y)| enemies.iter().all(|&(ex, ey)| (x - ex).abs() + (y - ey).abs() >= self.min_separation))
+            .collect();
+        if candidates.len() < side.count {
+            return Err(format!(
+                "only {} cells to place {} agents in a {:?} formation at least {} cells from the enemy",
+                candidates.len(),
+                side.count,
+                side.deployment.formation,
+                self.min_separation
+            ));
+        }
+
+        let enemy_centre = if enemies.is_empty() {
+            let zones: Vec<(f64, f64)> = sides
+                .iter()
+                .filter(|other| other.alliance != side.alliance)
+                .map(|other| other.deployment.zone.centre(arena))
+                .collect();
+            mean(&zones)
+        } else {
+            let cells: Vec<(f64, f64)> = enemies.iter().map(|&(x, y)| (x as f64 + 0.5, y as f64 + 0.5)).collect();
+            mean(&cells)
+        };
+        Ok(self.fill(arena, side.deployment, side.count, candidates, enemy_centre, rng))
+    }
+
+    /// Cells for reinforcements joining a battle under way: up to `side.count`
+    /// free cells in the side's zone, formed up to face the `enemies` still
+    /// standing. Fewer if the zone is too crowded. `min_separation` only
+    /// applies at the start of a battle.
+    pub fn reinforce(
+        &self,
+        arena: &Arena,
+        side: &Side,
+        taken: &HashSet<(i32, i32)>,
+        enemies: &[(i32, i32)],
+        rng: &mut impl Rng,
+    ) -> Positions {
+        let candidates: Vec<(i32, i32)> = self
+            .zone_cells(arena, side.deployment, side.mobility)
+            .into_iter()
+            .filter(|cell| !taken.contains(cell))
+            .collect();
+        let count = side.count.min(candidates.len());
+        let enemy_centre = if enemies.is_empty() {
+            side.deployment.zone.centre(arena)
+        } else {
+            let cells: Vec<(f64, f64)> = enemies.iter().map(|&(x, y)| (x as f64 + 0.5, y as f64 + 0.5)).collect();
+            mean(&cells)
+        };
+        self.fill(arena, side.deployment, count, candidates, enemy_centre, rng)
+    }
+
+    /// The `count` candidates a formation fills first, facing `enemy_centre`.
+    fn fill(
+        &self,
+        arena: &Arena,
+        deployment: &SideDeployment,
+        count: usize,
+        mut candidates: Vec<(i32, i32)>,
+        enemy_centre: (f64, f64),
+        rng: &mut impl Rng,
+    ) -> Positions {
+        if count == 0 {
+            return Vec::new();
+        }
+        let formation = deployment.formation;
+        let anchor = match formation {
+            Formation::Clustered => {
+                let (x, y) = *candidates.choose(rng).expect("candidates checked above");
+                (x as f64 + 0.5, y as f64 + 0.5)
+            }
+            _ => deployment.zone.centre(arena),
+        };
+
+        // Shuffle first so cells with equal keys are picked at random
+        candidates.shuffle(rng);
+        let key = formation_key(formation, anchor, enemy_centre, count, self.min_separation);
+        candidates.sort_by(|&a, &b| key(a).total_cmp(&key(b)));
+        candidates.truncate(count);
+        candidates
+    }
+}
+
+/// Average of a set of points, the origin if there are none.
+fn mean(points: &[(f64, f64)]) -> (f64, f64) {
+    let n = points.len().max(1) as f64;
+    (
+        points.iter().map(|p| p.0).sum::<f64>() / n,
+        points.iter().map(|p| p.1).sum::<f64>() / n,
+    )
+}
+
+/// Orders candidate cells for a formation; the lowest keys are filled first.
+fn formation_key(
+    formation: Formation,
+    anchor: (f64, f64),
+    enemy: (f64, f64),
+    count: usize,
+    min_separation: i32,
+) -> Box<dyn Fn((i32, i32)) -> f64> {
+    // Unit vector from the anchor towards the enemy, and the one across it
+    let (fx, fy) = (enemy.0 - anchor.0, enemy.1 - anchor.1);
+    let length = (fx * fx + fy * fy).sqrt();
+    let (fx, fy) = if length > 0.0 { (fx / length, fy / length) } else { (1.0, 0.0) };
+    let offsets = move |(x, y): (i32, i32)| {
+        let (dx, dy) = (x as f64 + 0.5 - anchor.0, y as f64 + 0.5 - anchor.1);
+        (dx * fx + dy * fy, dx * -fy + dy * fx)
+    };
+
+    match formation {
+        Formation::Scattered => Box::new(|_| 0.0),
+        Formation::Clustered => Box::new(move |cell| {
+            let (ahead, across) = offsets(cell);
+            ahead * ahead + across * across
+        }),
+        // One rank across the zone's centre, filled outwards, then ranks either side of it
+        Formation::Line => Box::new(move |cell| {
+            let (ahead, across) = offsets(cell);
+            ahead.round().abs() * 10_000.0 + across.abs()
+        }),
+        // Point at the zone's centre; each rank further back is a cell wider on
+        // either side. Cells outside the wedge only fill in if it doesn't fit.
+        Formation::Wedge => Box::new(move |cell| {
+            let (ahead, across) = offsets(cell);
+            let rank = (-ahead).round();
+            if rank >= 0.0 && across.abs() <= rank + 0.5 {
+                rank * 10_000.0 + across.abs()
+            } else {
+                1e9 + rank.abs() + across.abs()
+            }
+        }),
+        Formation::Ring => {
+            let radius = (count as f64 / (2.0 * PI)).max(min_separation as f64).max(1.0);
+            Box::new(move |(x, y)| {
+                let (dx, dy) = (x as f64 + 0.5 - enemy.0, y as f64 + 0.5 - enemy.1);
+                ((dx * dx + dy * dy).sqrt() - radius).abs()
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// The left quarter of the arena facing an enemy in the right quarter.
+    fn left(formation: Formation) -> SideDeployment {
+        SideDeployment { zone: SpawnZone { x: 0.0, width: 0.25, ..SpawnZone::default() }, formation }
+    }
+
+    fn right() -> SideDeployment {
+        SideDeployment { zone: SpawnZone { x: 0.75, width: 0.25, ..SpawnZone::default() }, ..SideDeployment::default() }
+    }
+
+    fn place(deployment: &Deployment, teams: &[(&SideDeployment, usize)]) -> Result<Vec<Positions>, String> {
+        let mobility = Mobility::default();
+        let sides: Vec<Side> = teams
+            .iter()
+            .enumerate()
+            .map(|(alliance, &(deployment, count))| Side { deployment, mobility: &mobility, count, alliance })
+            .collect();
+        deployment.place(&Arena::open(20, 20), &sides, &mut StdRng::seed_from_u64(7))
+    }
+
+    #[test]
+    fn a_line_forms_one_rank_across_the_zone_centre() {
+        let placed = place(&Deployment::default(), &[(&left(Formation::Line), 5), (&right(), 5)]).unwrap();
+        let line = &placed[0];
+        assert!(line.iter().all(|&(x, _)| x == 2), "{:?}", line);
+        let ys: Vec<i32> = line.iter().map(|&(_, y)| y).collect();
+        assert_eq!(ys.iter().max().unwrap() - ys.iter().min().unwrap(), 4, "{:?}", line);
+    }
+
+    #[test]
+    fn a_wedge_widens_behind_its_point() {
+        let placed = place(&Deployment::default(), &[(&left(Formation::Wedge), 5), (&right(), 5)]).unwrap();
+        let in_rank = |x| placed[0].iter().filter(|cell| cell.0 == x).count();
+        assert_eq!((in_rank(2), in_rank(1)), (2, 3), "{:?}", placed[0]);
+    }
+
+    #[test]
+    fn a_ring_surrounds_the_enemy_at_min_separation() {
+        let centre = SideDeployment {
+            zone: SpawnZone { x: 0.45, y: 0.45, width: 0.1, height: 0.1 },
+            ..SideDeployment::default()
+        };
+        let ring = SideDeployment { formation: Formation::Ring, ..SideDeployment::default() };
+        let deployment = Deployment { min_separation: 4, ..Deployment::default() };
+        let placed = place(&deployment, &[(&ring, 8), (&centre, 1)]).unwrap();
+
+        let (ex, ey) = placed[1][0];
+        let ring = &placed[0];
+        for &(x, y) in ring {
+            assert!((x - ex).abs() + (y - ey).abs() >= 4, "{:?} is too close to {:?}", (x, y), (ex, ey));
+            let distance = (((x - ex).pow(2) + (y - ey).pow(2)) as f64).sqrt();
+            assert!((distance - 4.0).abs() < 1.0, "{:?} is off the ring around {:?}", (x, y), (ex, ey));
+        }
+        assert!(ring.iter().any(|c| c.0 < ex) && ring.iter().any(|c| c.0 > ex));
+        assert!(ring.iter().any(|c| c.1 < ey) && ring.iter().any(|c| c.1 > ey));
+    }
+
+    #[test]
+    fn opponents_start_at_least_min_separation_apart() {
+        let anywhere = SideDeployment::default();
+        let deployment = Deployment { min_separation: 6, ..Deployment::default() };
+        let placed = place(&deployment, &[(&anywhere, 10), (&anywhere, 10)]).unwrap();
+        for &(x, y) in &placed[0] {
+            assert!(placed[1].iter().all(|&(ex, ey)| (x - ex).abs() + (y - ey).abs() >= 6));
+        }
+
+        let apart = Deployment { min_separation: 40, ..Deployment::default() };
+        let error = place(&apart, &[(&anywhere, 1), (&anywhere, 1)]).unwrap_err();
+        assert!(error.contains("at least 40 cells"), "{}", error);
+    }
+}
@@ -1,19 +1,27 @@
 use crate::arena::Arena;
-use crate::models::{Agent, BattleResult, Team};
+use crate::models::{Agent, BattleResult, SoloResult, Tactics};
 use crate::character_profiles::AgentProfile;
 use crate::causal::CausalMetrics;
+use crate::deployment::Formation;
+use crate::environment::BattleContext;
+use crate::scenario::{Engine, RoundMode};
+use crate::simulation::{BatchTables, SECONDS_PER_ROUND};
 use std::fs::OpenOptions;
 use std::io::Write;
 
+/// Runs one battle in a city from `tables`, with its opening weather and
+/// temperature. Load the tables once and share them between battles.
 pub fn run_battle(
     battle_id: usize,
     group_profile: &AgentProfile,
     solo_profile: &AgentProfile,
     group_count: usize,
     solo_count: usize,
+    tables: &BatchTables,
 ) -> BattleResult {
     let mut agents = Vec::new();
     let mut causal = CausalMetrics::new();
+    let mut arena = Arena::new();
 
     let mut file = OpenOptions::new()
         .create(true)
@@ -24,20 +32,19 @@ pub fn run_battle(
     writeln!(file, "Starting battle {} with {} group agents and {} solo agents", battle_id, group_count, solo_count).unwrap();
 
     for i in 0..group_count {
-        let (x, y) = Arena::random_position();
-        let mut agent = Agent::new_from_profile(i, Team::Group, x, y, group_profile);
+        let (x, y) = arena.random_position();
+        let mut agent = Agent::new_from_profile(i, 0, Tactics::Group, x, y, group_profile);
         agent.damage_dealt = 0;
         agents.push(agent);
     }
 
     for i in 0..solo_count {
-        let (x, y) = Arena::random_position();
-        let mut agent = Agent::new_from_profile(10000 + i, Team::Solo, x, y, solo_profile);
+        let (x, y) = arena.random_position();
+        let mut agent = Agent::new_from_profile(group_count + i, 1, Tactics::Solo, x, y, solo_profile);
         agent.damage_dealt = 0;
         agents.push(agent);
     }
 
-    let mut arena = Arena::new();
     let mut round = 0;
 
     while round < 1000 {
@@ -71,7 +78,7 @@ pub fn run_battle(
                 };
 
                 writeln!(file, "Agent {} (Team: {:?}) attacking Agent {} (Team: {:?})", 
-                    attacker.id, attacker.team, target.id, target.team).unwrap();
+                    attacker.id, attacker.tactics, target.id, target.tactics).unwrap();
 
                 let (crit, damage_dealt) = attacker.attack(target);
                 if crit {
@@ -86,8 +93,9 @@ pub fn run_battle(
                 }
 
                 if !target.alive {
-                    causal.solo_final_blow = attacker.team == Team::Solo;
-                    writeln!(file, "Agent {} killed! Final blow by {:?}", target.id, attacker.team).unwrap();
+                    attacker.kills += 1;
+                    causal.solo_final_blow = attacker.tactics == Tactics::Solo;
+                    writeln!(file, "Agent {} killed! Final blow by {:?}", target.id, attacker.tactics).unwrap();
                 }
             }
         }
@@ -103,23 +111,29 @@ pub fn run_battle(
         }
     }
 
-    let group_alive = agents.iter().any(|a| a.alive && a.team == Team::Group);
-    let solo_alive = agents.iter().any(|a| a.alive && a.team == Team::Solo);
-    let group_casualties = group_count as u32 - agents.iter().filter(|a| a.alive && a.team == Team::Group).count() as u32;
+    let group_alive = agents.iter().any(|a| a.alive && a.tactics == Tactics::Group);
+    let solos: Vec<SoloResult> = agents
+        .iter()
+        .filter(|a| a.tactics == Tactics::Solo)
+        .map(|a| SoloResult::of(a, "Solo"))
+        .collect();
+    let solos_alive = solos.iter().filter(|s| s.survived).count() as u32;
+    let solo_alive = solos_alive > 0;
+    let group_casualties = group_count as u32 - agents.iter().filter(|a| a.alive && a.tactics == Tactics::Group).count() as u32;
 
     let winner = if solo_alive && !group_alive {
-        Team::Solo
+        "Solo"
     } else {
-        Team::Group
+        "Group"
     };
 
     writeln!(file, "\nFinal stats:").unwrap();
-    writeln!(file, "Winner: {:?}", winner).unwrap();
+    writeln!(file, "Winner: {}", winner).unwrap();
     writeln!(file, "Group casualties: {}", group_casualties).unwrap();
     writeln!(file, "Solo survived: {}", solo_alive).unwrap();
 
     let group_damage: Vec<f32> = agents.iter()
-        .filter(|a| a.team == Team::Group)
+        .filter(|a| a.tactics == Tactics::Group)
         .map(|a| a.damage_dealt as f32)
         .collect();
 
@@ -131,9 +145,8 @@ pub fn run_battle(
 
     causal.max_group_damage = group_damage.into_iter().fold(0.0, |a, b| a.max(b));
 
-    if let Some(solo) = agents.iter().find(|a| a.team == Team::Solo) {
-        causal.solo_end_hp = solo.hp.max(0) as u32;
-    }
+    causal.solo_end_hp = solos.first().map_or(0, |s| s.end_hp);
+    causal.solos_total_end_hp = solos.iter().map(|s| s.end_hp).sum();
 
     writeln!(file, "Causal metrics:").unwrap();
     writeln!(file, "Total critical hits: {}", causal.total_critical_hits).unwrap();
@@ -143,19 +156,40 @@ pub fn run_battle(
     writeln!(file, "Rounds engaged: {}", causal.rounds_engaged).unwrap();
     writeln!(file, "Solo final blow: {}", causal.solo_final_blow).unwrap();
 
+    let mut rng = rand::thread_rng();
     BattleResult {
         battle_id,
-        winner,
+        winner: winner.to_string(),
         rounds: round,
         group_casualties,
         solo_survived: solo_alive,
-        context: crate::environment::BattleContext::random_from_file("realistic_cities_with_climate.csv"),
+        context: BattleContext::at(
+            tables.locations.pick(battle_id, &mut rng),
+            &tables.weather_odds,
+            &tables.temperatures,
+            None,
+            None,
+            &mut rng,
+        ),
         causal,
+        round_mode: RoundMode::Sequential,
+        engine: Engine::Rounds,
+        elapsed_seconds: round as f64 * SECONDS_PER_ROUND,
+        map: arena.name().to_string(),
+        density: (group_count + solo_count) as f64 / arena.usable_cells() as f64,
+        group_formation: Formation::Scattered,
+        solo_formation: Formation::Scattered,
+        weather_timeline: Vec::new(),
+        teams: Vec::new(),
+        arrivals: Vec::new(),
+        solos,
+        solos_alive,
+        seed: 0,
     }
 }
 
 fn is_battle_over(agents: &[Agent]) -> bool {
-    let group_alive = agents.iter().any(|a| a.alive && a.team == Team::Group);
-    let solo_alive = agents.iter().any(|a| a.alive && a.team == Team::Solo);
+    let group_alive = agents.iter().any(|a| a.alive && a.tactics == Tactics::Group);
+    let solo_alive = agents.iter().any(|a| a.alive && a.tactics == Tactics::Solo);
     !(group_alive && solo_alive)
 }
\ No newline at end of file
@@ -1,8 +1,12 @@
 use rand::seq::SliceRandom;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File;
 use std::io::BufReader;
+use crate::thermal::TemperatureTable;
+use crate::solar::{format_date, light_level, local_to_utc, solar_elevation, LightPhase};
+use crate::weather::WeatherDistributions;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct CityClimateEntry {
@@ -22,23 +26,168 @@ pub struct BattleContext {
     pub climate: String,
     pub weather: String,
     pub is_day: bool,
+    /// Battle date as "MM-DD".
+    #[serde(default)]
+    pub date: String,
+    #[serde(default)]
+    pub utc_hour: f64,
+    /// Local mean solar time at the battle location.
+    #[serde(default)]
+    pub local_hour: f64,
+    /// Sun elevation above the horizon in degrees.
+    #[serde(default)]
+    pub solar_elevation: f64,
+    /// 0 (dark) to 1 (full daylight).
+    #[serde(default)]
+    pub light_level: f64,
+    #[serde(default)]
+    pub light: LightPhase,
+    /// Ambient temperature in °C.
+    #[serde(default)]
+    pub temperature_c: f64,
 }
 
-impl BattleContext {
-    pub fn random_from_file(path: &str) -> Self {
+/// Latitude/longitude rectangle in degrees. A `min_lon` above `max_lon`
+/// wraps across the antimeridian.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub min_lat: f64,
+    pub max_lat: f64,
+    pub min_lon: f64,
+    pub max_lon: f64,
+}
+
+impl BoundingBox {
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        let within_lon = if self.min_lon <= self.max_lon {
+            (self.min_lon..=self.max_lon).contains(&longitude)
+        } else {
+            longitude >= self.min_lon || longitude <= self.max_lon
+        };
+        (self.min_lat..=self.max_lat).contains(&latitude) && within_lon
+    }
+}
+
+/// Which cities battles are fought in and how they are sampled. Empty lists
+/// don't filter, and names match case-insensitively.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocationConfig {
+    pub climates: Vec<String>,
+    pub countries: Vec<String>,
+    pub bounds: Option<BoundingBox>,
+    /// Fight every battle in this city, by its full name ("Tromsø_6722") or
+    /// the name before the suffix ("Tromsø").
+    pub city: Option<String>,
+    /// Give each climate an equal share of the battles instead of sampling
+    /// cities uniformly.
+    pub stratify: bool,
+}
+
+impl LocationConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(b) = &self.bounds {
+            if !(-90.0..=90.0).contains(&b.min_lat) || !(-90.0..=90.0).contains(&b.max_lat) || b.min_lat > b.max_lat {
+                return Err(format!("invalid latitude bounds {} to {}", b.min_lat, b.max_lat));
+            }
+            if !(-180.0..=180.0).contains(&b.min_lon) || !(-180.0..=180.0).contains(&b.max_lon) {
+                return Err(format!("invalid longitude bounds {} to {}", b.min_lon, b.max_lon));
+            }
+        }
+        if self.stratify && self.city.is_some() {
+            return Err("stratify can't be combined with a pinned city".to_string());
+        }
+        Ok(())
+    }
+
+    fn matches(&self, entry: &CityClimateEntry) -> bool {
+        let listed = |list: &[String], value: &str| list.is_empty() || list.iter().any(|v| v.eq_ignore_ascii_case(value));
+        listed(&self.climates, &entry.climate)
+            && listed(&self.countries, &entry.country)
+            && self.bounds.as_ref().is_none_or(|b| b.contains(entry.latitude, entry.longitude))
+            && self.city.as_ref().is_none_or(|city| {
+                let base = entry.city.rsplit_once('_').map_or(entry.city.as_str(), |(base, _)| base);
+                city.to_lowercase() == entry.city.to_lowercase() || city.to_lowercase() == base.to_lowercase()
+            })
+    }
+}
+
+/// The city table, loaded once per batch.
+pub struct CityTable {
+    entries: Vec<CityClimateEntry>,
+}
+
+impl CityTable {
+    pub fn load(path: &str) -> Self {
         let file = File::open(path).expect("Failed to open climate CSV");
         let mut rdr = csv::Reader::from_reader(BufReader::new(file));
-        let entries: Vec<CityClimateEntry> = rdr
+        let entries = rdr
             .deserialize()
             .map(|res| res.expect("Failed to parse row"))
             .collect();
+        CityTable { entries }
+    }
+
+    /// Every climate label in the table.
+    pub fn climates(&self) -> BTreeSet<&str> {
+        self.entries.iter().map(|e| e.climate.as_str()).collect()
+    }
+
+    /// Sampler over the cities that match `config`.
+    pub fn sampler(&self, config: &LocationConfig) -> Result<LocationSampler<'_>, String> {
+        let matching: Vec<&CityClimateEntry> = self.entries.iter().filter(|e| config.matches(e)).collect();
+        if matching.is_empty() {
+            return Err(format!("no cities match the location settings {:?}", config));
+        }
+
+        let strata = if config.stratify {
+            let mut by_climate: BTreeMap<&str, Vec<&CityClimateEntry>> = BTreeMap::new();
+            for entry in matching {
+                by_climate.entry(&entry.climate).or_default().push(entry);
+            }
+            by_climate.into_values().collect()
+        } else {
+            vec![matching]
+        };
+        Ok(LocationSampler { strata })
+    }
+}
 
-        let mut rng = rand::thread_rng();
-        let entry = entries.choose(&mut rng).expect("No entries in climate CSV");
+/// Picks the city for each battle.
+pub struct LocationSampler<'a> {
+    /// One group of cities per climate when stratified, otherwise just one.
+    strata: Vec<Vec<&'a CityClimateEntry>>,
+}
+
+impl LocationSampler<'_> {
+    /// Battles take turns between the strata by id, so each gets an equal
+    /// share, and pick a city at random within it.
+    pub fn pick(&self, battle_id: usize, rng: &mut impl Rng) -> &CityClimateEntry {
+        let stratum = &self.strata[battle_id % self.strata.len()];
+        stratum.choose(rng).expect("strata are never empty")
+    }
+}
 
-        let weather_options = get_weather_options(&entry.climate);
-        let weather = weather_options.choose(&mut rng).unwrap_or(&"Clear").to_string();
-        let is_day = rng.gen_bool(0.5);
+impl BattleContext {
+    /// Conditions for a battle in `entry`'s city, with the opening weather
+    /// drawn from `weather` and the temperature from `temperatures`. The date
+    /// (day of year) and local solar hour are sampled uniformly unless given.
+    pub fn at(
+        entry: &CityClimateEntry,
+        weather: &WeatherDistributions,
+        temperatures: &TemperatureTable,
+        day_of_year: Option<u32>,
+        local_hour: Option<f64>,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let day_of_year = day_of_year.unwrap_or_else(|| rng.gen_range(1..=365));
+        let weather = weather.sample(&entry.climate, day_of_year, rng);
+        let local_hour = local_hour.unwrap_or_else(|| rng.gen_range(0.0..24.0));
+        let (utc_day, utc_hour) = local_to_utc(day_of_year, local_hour, entry.longitude);
+        let solar_elevation = solar_elevation(entry.latitude, entry.longitude, utc_day, utc_hour);
+        let light = LightPhase::from_elevation(solar_elevation);
+        let temperature_c =
+            temperatures.sample(&entry.climate, &weather, entry.latitude, day_of_year, local_hour, rng);
 
         BattleContext {
             location_name: entry.city.clone(),
@@ -47,18 +196,165 @@ impl BattleContext {
             longitude: entry.longitude,
             climate: entry.climate.clone(),
             weather,
-            is_day,
+            is_day: light == LightPhase::Day,
+            date: format_date(day_of_year),
+            utc_hour,
+            local_hour,
+            solar_elevation,
+            light_level: light_level(solar_elevation),
+            light,
+            temperature_c,
         }
     }
 }
 
-fn get_weather_options(climate: &str) -> Vec<&'static str> {
-    match climate {
-        "Tropical" => vec!["Humid", "Rain", "Clear", "Storm"],
-        "Arid" => vec!["Clear", "Windy", "Dusty", "Hot"],
-        "Temperate" => vec!["Clear", "Rain", "Cloudy", "Windy"],
-        "Continental" => vec!["Snow", "Overcast", "Clear", "Rain"],
-        "Polar" => vec!["Snow", "Blizzard", "Freezing Fog", "Clear"],
-        _ => vec!["Clear"],
+/// Combat effects of battle conditions. Every field defaults to "no effect".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvironmentModifiers {
+    /// Percentage points added to every hit chance.
+    pub hit_modifier: i32,
+    /// Percent chance an agent loses its action to a slip.
+    pub slip_chance: i32,
+    /// Rounds of movement needed per cell.
+    pub move_cost: f32,
+    /// Scales the fatigue penalty.
+    pub fatigue_multiplier: f32,
+    /// Scales every agent's sight radius.
+    pub visibility: f32,
+}
+
+impl Default for EnvironmentModifiers {
+    fn default() -> Self {
+        EnvironmentModifiers {
+            hit_modifier: 0,
+            slip_chance: 0,
+            move_cost: 1.0,
+            fatigue_multiplier: 1.0,
+            visibility: 1.0,
+        }
     }
-} 
\ No newline at end of file
+}
+
+impl EnvironmentModifiers {
+    /// Scales every effect towards "no effect" by `weight` (0 to 1).
+    pub fn scaled(&self, weight: f32) -> Self {
+        EnvironmentModifiers {
+            hit_modifier: (self.hit_modifier as f32 * weight).round() as i32,
+            slip_chance: (self.slip_chance as f32 * weight).round() as i32,
+            move_cost: 1.0 + (self.move_cost - 1.0) * weight,
+            fatigue_multiplier: 1.0 + (self.fatigue_multiplier - 1.0) * weight,
+            visibility: 1.0 + (self.visibility - 1.0) * weight,
+        }
+    }
+
+    /// Stacks another set of modifiers on top: additive fields add, scaling fields multiply.
+    pub fn combine(&self, other: &EnvironmentModifiers) -> Self {
+        EnvironmentModifiers {
+            hit_modifier: self.hit_modifier + other.hit_modifier,
+            slip_chance: (self.slip_chance + other.slip_chance).clamp(0, 100),
+            move_cost: self.move_cost * other.move_cost,
+            fatigue_multiplier: self.fatigue_multiplier * other.fatigue_multiplier,
+            visibility: self.visibility * other.visibility,
+        }
+    }
+}
+
+/// Modifiers in effect for one battle and the table entries they came from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppliedModifiers {
+    pub sources: Vec<String>,
+    pub combined: EnvironmentModifiers,
+}
+
+/// Modifier table loaded from `environment_modifiers.json`, keyed by weather
+/// and climate label, plus a set applied to night battles.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ModifierTable {
+    pub weather: HashMap<String, EnvironmentModifiers>,
+    pub climate: HashMap<String, EnvironmentModifiers>,
+    pub night: EnvironmentModifiers,
+}
+
+impl ModifierTable {
+    pub fn load(path: &str) -> Self {
+        let file = File::open(path).expect("Could not open environment modifier file");
+        let reader = BufReader::new(file);
+        serde_json::from_reader(reader).expect("Failed to deserialize environment modifiers")
+    }
+
+    /// Stacks the climate, weather and night entries that match `context`.
+    /// The night entry is weighted by how dark it is, so twilight gets part of
+    /// it. Labels without an entry have no effect.
+    pub fn modifiers_for(&self, context: &BattleContext) -> AppliedModifiers {
+        let mut applied = AppliedModifiers::default();
+        if let Some(m) = self.climate.get(&context.climate) {
+            applied.combined = applied.combined.combine(m);
+            applied.sources.push(format!("climate:{}", context.climate));
+        }
+        if let Some(m) = self.weather.get(&context.weather) {
+            applied.combined = applied.combined.combine(m);
+            applied.sources.push(format!("weather:{}", context.weather));
+        }
+        let darkness = 1.0 - context.light_level as f32;
+        if darkness > 0.0 {
+            applied.combined = applied.combined.combine(&self.night.scaled(darkness));
+            applied.sources.push(format!("night:{:.2}", darkness));
+        }
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> ModifierTable {
+        serde_json::from_str(
+            r#"{
+                "climate": { "Polar": { "move_cost": 1.25, "fatigue_multiplier": 1.1 } },
+                "weather": { "Rain": { "hit_modifier": -5, "slip_chance": 10, "move_cost": 1.2, "visibility": 0.8 } },
+                "night": { "hit_modifier": -10, "slip_chance": 4, "visibility": 0.5 }
+            }"#,
+        )
+        .expect("test table parses")
+    }
+
+    fn context(climate: &str, weather: &str, light_level: f64) -> BattleContext {
+        let json = serde_json::json!({
+            "location_name": "Tromsø", "country": "Norway", "latitude": 69.6, "longitude": 18.9,
+            "climate": climate, "weather": weather, "is_day": light_level >= 1.0, "light_level": light_level,
+        });
+        serde_json::from_value(json).expect("test context parses")
+    }
+
+    #[test]
+    fn combining_adds_offsets_and_multiplies_scales() {
+        let slippery = EnvironmentModifiers { hit_modifier: -5, slip_chance: 70, move_cost: 2.0, ..Default::default() };
+        let dim = EnvironmentModifiers { hit_modifier: 3, slip_chance: 50, visibility: 0.5, move_cost: 1.5, ..Default::default() };
+        let both = slippery.combine(&dim);
+        assert_eq!((both.hit_modifier, both.slip_chance), (-2, 100));
+        assert_eq!((both.move_cost, both.visibility, both.fatigue_multiplier), (3.0, 0.5, 1.0));
+        assert_eq!(EnvironmentModifiers::default().combine(&dim), dim);
+    }
+
+    #[test]
+    fn matching_entries_stack_with_night_weighted_by_darkness() {
+        let table = table();
+        let day = table.modifiers_for(&context("Polar", "Rain", 1.0));
+        assert_eq!(day.sources, ["climate:Polar", "weather:Rain"]);
+        assert_eq!((day.combined.hit_modifier, day.combined.slip_chance), (-5, 10));
+        assert!((day.combined.move_cost - 1.5).abs() < 1e-6);
+        assert!((day.combined.fatigue_multiplier - 1.1).abs() < 1e-6);
+
+        let dusk = table.modifiers_for(&context("Polar", "Rain", 0.5));
+        assert_eq!(dusk.sources, ["climate:Polar", "weather:Rain", "night:0.50"]);
+        assert_eq!((dusk.combined.hit_modifier, dusk.combined.slip_chance), (-10, 12));
+        assert!((dusk.combined.visibility - 0.6).abs() < 1e-6);
+
+        let unlisted = table.modifiers_for(&context("Tropical", "Clear", 1.0));
+        assert!(unlisted.sources.is_empty());
+        assert_eq!(unlisted.combined, EnvironmentModifiers::default());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::arena::get_occupied_positions;
+use crate::causal::CausalMetrics;
+use crate::environment::EnvironmentModifiers;
+use crate::models::Agent;
+use crate::perception;
+use crate::simulation::{
+    fatigue_penalty, in_contact, lost_contact, plan_action, simulation_active, strike, Action, Battlefield, Reinforcements,
+    MAX_ROUNDS, SECONDS_PER_ROUND, STALL_ROUNDS,
+};
+use crate::squads::Command;
+use crate::weather::WEATHER_STEP_SECONDS;
+use rand::Rng;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Speed at which an action takes exactly one round's worth of time.
+const REFERENCE_SPEED: f64 = 10.0;
+
+/// The clock runs in whole milliseconds so events order exactly.
+type Millis = u64;
+
+fn to_millis(seconds: f64) -> Millis {
+    (seconds * 1000.0).round() as Millis
+}
+
+/// Events are ordered by time, then weather, command ticks and actions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Event {
+    /// The weather chain takes a step.
+    Weather,
+    /// Once per round's worth of time: reinforcements arrive, leaders issue
+    /// orders, orders in transit arrive, and fatigue and stalling are accounted for.
+    CommandTick(u32),
+    /// Agent (by index) takes its next action.
+    Act(usize),
+}
+
+/// Seconds an agent's next action takes. An agent at `REFERENCE_SPEED` acts
+/// once per round; faster agents act more often and fatigue slows everyone.
+pub fn action_seconds(agent: &Agent, rounds_engaged: u32, effects: &EnvironmentModifiers) -> f64 {
+    let effective_speed = (agent.spd - fatigue_penalty(agent, rounds_engaged, effects)).max(1);
+    SECONDS_PER_ROUND * REFERENCE_SPEED / effective_speed as f64
+}
+
+/// When an agent entering the battle at `now` first acts. First actions are
+/// staggered so equally fast agents don't act in lockstep.
+fn first_action(agent: &Agent, now: Millis, rounds_engaged: u32, effects: &EnvironmentModifiers, rng: &mut impl Rng) -> Millis {
+    now + to_millis(rng.gen_range(0.0..action_seconds(agent, rounds_engaged, effects)))
+}
+
+/// Next-event engine: each agent acts whenever its previous action finishes,
+/// using the same action rules as the round engine. Returns the number of
+/// rounds' worth of time that passed and the elapsed simulated seconds.
+pub fn run_events(
+    agents: &mut Vec<Agent>,
+    command: &mut Command,
+    causal: &mut CausalMetrics,
+    field: &mut Battlefield,
+    reinforcements: &mut Reinforcements,
+) -> (u32, f64) {
+    let mut rng = rand::thread_rng();
+    let round_millis = to_millis(SECONDS_PER_ROUND);
+    let weather_millis = to_millis(WEATHER_STEP_SECONDS);
+    let mut queue = BinaryHeap::new();
+
+    queue.push(Reverse((0, Event::CommandTick(1))));
+    queue.push(Reverse((weather_millis, Event::Weather)));
+    for (i, agent) in agents.iter().enumerate() {
+        queue.push(Reverse((first_action(agent, 0, 0, &field.effects, &mut rng), Event::Act(i))));
+    }
+
+    let mut occupied = get_occupied_positions(agents);
+    // When each agent last took a blow. The group only lands one blow per
+    // target per round's worth of time, mirroring the round engine.
+    let mut recent_hits: HashMap<usize, Millis> = HashMap::new();
+    let mut damage_since_tick = false;
+    let mut consecutive_no_damage = 0;
+    let mut rounds_searching = 0;
+    let mut rounds = 0;
+    let mut now = 0;
+
+    while let Some(Reverse((time, event))) = queue.pop() {
+        if !simulation_active(agents, reinforcements, rounds) {
+            break;
+        }
+        now = time;
+
+        match event {
+            Event::Weather => {
+                field.step_weather(agents, now as f64 / 1000.0, &mut rng);
+                queue.push(Reverse((now + weather_millis, Event::Weather)));
+            }
+            Event::CommandTick(round) => {
+                if round > MAX_ROUNDS {
+                    break;
+                }
+                rounds = round;
+                if damage_since_tick {
+                    causal.rounds_engaged += 1;
+                    consecutive_no_damage = 0;
+                } else if in_contact(agents) {
+                    consecutive_no_damage += 1;
+                    if consecutive_no_damage >= STALL_ROUNDS {
+                        break;
+                    }
+                }
+                damage_since_tick = false;
+                if lost_contact(agents, reinforcements, round, &mut rounds_searching) {
+                    break;
+                }
+
+                let arrived = reinforcements.arrive(agents, command, round, now as f64 / 1000.0, field, &mut rng);
+                for i in arrived {
+                    occupied.insert((agents[i].x, agents[i].y));
+                    let first = first_action(&agents[i], now, causal.rounds_engaged, &field.effects, &mut rng);
+                    queue.push(Reverse((first, Event::Act(i))));
+                }
+                command.issue_orders(agents, round);
+                queue.push(Reverse((now + round_millis, Event::CommandTick(round + 1))));
+            }
+            Event::Act(i) => {
+                if !agents[i].alive {
+                    continue;
+                }
+                perception::observe(agents, i);
+                perception::hear(agents, i);
+
+                let targeted_agents: HashSet<usize> = recent_hits
+                    .iter()
+                    .filter(|(_, &hit)| now - hit < round_millis)
+                    .map(|(&target, _)| target)
+                    .collect();
+                match plan_action(agents, i, command, causal.rounds_engaged, &targeted_agents, field, &mut rng) {
+                    Action::Hold => {}
+                    Action::Slip => causal.slips += 1,
+                    Action::Search => agents[i].search(&mut occupied, field.arena, field.effects.move_cost),
+                    Action::Move(tx, ty) => {
+                        agents[i].move_towards_coords(tx, ty, &mut occupied, field.arena, field.effects.move_cost)
+                    }
+                    Action::Attack { target, damage, crit } => {
+                        if damage > 0 {
+                            if crit {
+                                causal.total_critical_hits += 1;
+                            }
+                            recent_hits.insert(target, now);
+                            damage_since_tick = true;
+                            if strike(agents, i, target, damage) {
+                                occupied.remove(&(agents[target].x, agents[target].y));
+                            }
+                        }
+                    }
+                }
+
+                let duration = action_seconds(&agents[i], causal.rounds_engaged, &field.effects);
+                queue.push(Reverse((now + to_millis(duration), Event::Act(i))));
+            }
+        }
+    }
+
+    (rounds, now as f64 / 1000.0)
+}
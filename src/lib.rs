@@ -4,8 +4,17 @@ pub mod models;
 pub mod output;
 pub mod simulation;
 pub mod character_profiles;
-pub mod combatants;
 pub mod environment;
 pub mod analysis;
 pub mod causal;
-pub mod engine; 
\ No newline at end of file
+pub mod engine;
+pub mod scenario;
+pub mod squads;
+pub mod perception;
+pub mod pathfinding;
+pub mod events;
+pub mod solar;
+pub mod thermal;
+pub mod terrain;
+pub mod deployment;
+pub mod weather;
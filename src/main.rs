@@ -1,6 +1,8 @@
 use std::env;
-use battle_sim::simulation::run_batch_simulation;
+use battle_sim::simulation::{run_batch_simulation, run_density_sweep};
 use battle_sim::analysis::{load_results_from_file, run_analysis};
+use battle_sim::scenario::Scenario;
+use battle_sim::character_profiles::{ProfileRegistry, PROFILES_FILE};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -8,7 +10,10 @@ fn main() {
         println!("Usage: {} <command> [options]", args[0]);
         println!("Commands:");
         println!("  simulate <count> <batch_id> [group_profile] [solo_profile] [group_count] [solo_count]");
+        println!("  run <scenario_file> <count> <batch_id>");
+        println!("  sweep <scenario_file> <count> <batch_id> <density>...");
         println!("  analyze <results_file>");
+        println!("  profiles list|validate|show <name>");
         return;
     }
 
@@ -20,12 +25,38 @@ fn main() {
             }
             let count: usize = args[2].parse().expect("Invalid count");
             let batch_id: usize = args[3].parse().expect("Invalid batch_id");
-            let group_profile = args.get(4).map_or("man", |s| s.as_str());
-            let solo_profile = args.get(5).map_or("gorilla", |s| s.as_str());
-            let group_count: usize = args.get(6).map_or(100, |s| s.parse().expect("Invalid group_count"));
-            let solo_count: usize = args.get(7).map_or(1, |s| s.parse().expect("Invalid solo_count"));
+            let defaults = Scenario::default();
+            let scenario = Scenario {
+                group_profile: args.get(4).cloned().unwrap_or(defaults.group_profile),
+                solo_profile: args.get(5).cloned().unwrap_or(defaults.solo_profile),
+                group_count: args.get(6).map_or(defaults.group_count, |s| s.parse().expect("Invalid group_count")),
+                solo_count: args.get(7).map_or(defaults.solo_count, |s| s.parse().expect("Invalid solo_count")),
+                ..Scenario::default()
+            };
+            exit_on_error(run_batch_simulation(count, batch_id, &scenario));
+        }
+        "run" => {
+            if args.len() < 5 {
+                println!("Usage: {} run <scenario_file> <count> <batch_id>", args[0]);
+                return;
+            }
+            let scenario = exit_on_error(Scenario::load(&args[2]));
+            let count: usize = args[3].parse().expect("Invalid count");
+            let batch_id: usize = args[4].parse().expect("Invalid batch_id");
+
+            exit_on_error(run_batch_simulation(count, batch_id, &scenario));
+        }
+        "sweep" => {
+            if args.len() < 6 {
+                println!("Usage: {} sweep <scenario_file> <count> <batch_id> <density>...", args[0]);
+                return;
+            }
+            let scenario = exit_on_error(Scenario::load(&args[2]));
+            let count: usize = args[3].parse().expect("Invalid count");
+            let batch_id: usize = args[4].parse().expect("Invalid batch_id");
+            let densities: Vec<f64> = args[5..].iter().map(|d| d.parse().expect("Invalid density")).collect();
 
-            run_batch_simulation(count, batch_id, group_profile, solo_profile, group_count, solo_count);
+            exit_on_error(run_density_sweep(count, batch_id, &scenario, &densities));
         }
         "analyze" => {
             if args.len() < 3 {
@@ -43,9 +74,57 @@ fn main() {
                         .unwrap_or(0);
                     run_analysis(&results, batch_id);
                 }
-                Err(e) => println!("Failed to load results: {}", e),
+                Err(e) => exit_on_error(Err(format!("Failed to load results: {}", e))),
             }
         }
+        "profiles" => match (args.get(2).map(String::as_str), args.get(3)) {
+            (Some("list"), _) => {
+                let profiles = exit_on_error(ProfileRegistry::load(PROFILES_FILE));
+                println!("Profiles in {} and the built-in library v{}:", profiles.path, profiles.library_version);
+                for (name, profile) in profiles.iter() {
+                    let parent = profiles.parent(name).map_or(String::new(), |p| format!(" (extends {})", p));
+                    let built_in = if profiles.is_built_in(name) { " [built-in]" } else { "" };
+                    println!("- {}{}{}: {}", name, built_in, parent, profile.summary());
+                    if let Some(provenance) = &profile.provenance {
+                        println!("    {} confidence, {}: {}", provenance.confidence, provenance.date, provenance.source);
+                    }
+                }
+            }
+            (Some("validate"), _) => {
+                let profiles = ProfileRegistry::read(PROFILES_FILE)
+                    .map_err(|e| format!("Invalid profiles in {}: {}", PROFILES_FILE, e));
+                let profiles = exit_on_error(profiles);
+                for warning in profiles.warnings() {
+                    println!("Warning: {}", warning);
+                }
+                println!(
+                    "{} profiles in {} and the built-in library v{} are valid",
+                    profiles.iter().count(),
+                    profiles.path,
+                    profiles.library_version
+                );
+            }
+            (Some("show"), Some(query)) => {
+                let profiles = exit_on_error(ProfileRegistry::load(PROFILES_FILE));
+                let (name, profile) = exit_on_error(profiles.find(query));
+                println!("{}", serde_json::to_string_pretty(profile).expect("Failed to serialize profile"));
+                if name != query {
+                    println!("Profile: {}", name);
+                }
+                for derivation in profile.derivations() {
+                    println!("Derived: {}", derivation);
+                }
+            }
+            _ => println!("Usage: {} profiles list|validate|show <name>", args[0]),
+        },
         _ => println!("Unknown command: {}", args[1]),
     }
 }
+
+/// The value, or prints the error and exits with status 1.
+fn exit_on_error<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
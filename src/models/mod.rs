@@ -1,18 +1,34 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use crate::environment::BattleContext;
 use crate::causal::CausalMetrics;
+use crate::deployment::Formation;
+use crate::scenario::{Engine, RoundMode};
+use crate::terrain::Mobility;
+use crate::weather::WeatherChange;
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub enum Team {
-    Group,  // Human group
-    Solo,   // Gorilla
+/// How a team fights.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Tactics {
+    /// A crowd that coordinates, forms squads and piles onto one target at a time.
+    #[default]
+    Group,
+    /// Lone fighters relying on raw strength, who tire faster.
+    Solo,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Agent {
     pub id: usize,
-    pub team: Team,
+    /// Index of the agent's team in the battle.
+    pub team: usize,
+    /// Index of the agent's entry in its team's members.
+    pub member: usize,
+    /// Agents in the same alliance never fight each other.
+    pub alliance: usize,
+    pub tactics: Tactics,
     pub hp: i32,
+    pub max_hp: i32,
     pub str_: i32,
     pub spd: i32,
     pub def: i32,
@@ -22,14 +38,40 @@ pub struct Agent {
     pub y: i32,
     pub alive: bool,
     pub damage_dealt: u32,
+    /// Enemies this agent landed the killing blow on.
+    pub kills: u32,
+    pub int_abstract: i32,
+    pub squad_id: Option<usize>,
+    pub order_target: Option<usize>,
+    pub int_environmental: i32,
+    pub sight_radius: i32,
+    /// Last known position of each enemy this agent is aware of, by index.
+    pub memory: HashMap<usize, (i32, i32)>,
+    /// Cached route to `path_goal`, next step last.
+    pub path: Vec<(i32, i32)>,
+    pub path_goal: Option<(i32, i32)>,
+    /// Cell the agent is sweeping towards while it knows of no enemy.
+    pub search_goal: Option<(i32, i32)>,
+    /// Movement built up towards the cost of the next step.
+    pub move_progress: f32,
+    pub endurance: i32,
+    pub heat_tolerance: i32,
+    pub cold_tolerance: i32,
+    /// Multiplier on fatigue from the battle's temperature.
+    pub thermal_strain: f32,
+    pub mobility: Mobility,
 }
 
 impl Default for Agent {
     fn default() -> Self {
         Agent {
             id: 0,
-            team: Team::Group,
+            team: 0,
+            member: 0,
+            alliance: 0,
+            tactics: Tactics::Group,
             hp: 0,
+            max_hp: 0,
             str_: 0,
             spd: 0,
             def: 0,
@@ -39,17 +81,160 @@ impl Default for Agent {
             y: 0,
             alive: true,
             damage_dealt: 0,
+            kills: 0,
+            int_abstract: 0,
+            squad_id: None,
+            order_target: None,
+            int_environmental: 0,
+            sight_radius: 0,
+            memory: HashMap::new(),
+            path: Vec::new(),
+            path_goal: None,
+            search_goal: None,
+            move_progress: 0.0,
+            endurance: 0,
+            heat_tolerance: 0,
+            cold_tolerance: 0,
+            thermal_strain: 1.0,
+            mobility: Mobility::default(),
         }
     }
 }
 
+impl Agent {
+    pub fn is_enemy_of(&self, other: &Agent) -> bool {
+        self.alliance != other.alliance
+    }
+}
+
+/// `BattleResult::winner` when no alliance came out ahead: every side fell,
+/// or the battle stalled with more than one side standing.
+pub const DRAW: &str = "Draw";
+
+/// `BattleResult::winner` when the round limit cut the battle off with more
+/// than one side standing.
+pub const TIMEOUT: &str = "Timeout";
+
+/// How the agents of one profile in a team fared.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemberResult {
+    pub profile: String,
+    pub count: u32,
+    pub survivors: u32,
+    pub damage_dealt: u32,
+}
+
+/// How one agent fighting with solo tactics fared.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoloResult {
+    pub id: usize,
+    pub team: String,
+    pub survived: bool,
+    pub end_hp: u32,
+    pub max_hp: u32,
+    pub damage_dealt: u32,
+    pub kills: u32,
+    /// Elevation it finished on, 0 for the ground.
+    #[serde(default)]
+    pub end_elevation: u32,
+}
+
+impl SoloResult {
+    pub fn of(agent: &Agent, team: &str) -> Self {
+        SoloResult {
+            id: agent.id,
+            team: team.to_string(),
+            survived: agent.alive,
+            end_hp: if agent.alive { agent.hp.max(0) as u32 } else { 0 },
+            max_hp: agent.max_hp.max(0) as u32,
+            damage_dealt: agent.damage_dealt,
+            kills: agent.kills,
+            end_elevation: 0,
+        }
+    }
+}
+
+/// One arrival of a reinforcement wave.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Arrival {
+    pub team: String,
+    pub profile: String,
+    pub round: u32,
+    /// Simulated seconds since the battle started.
+    pub seconds: f64,
+    /// Agents that arrived. Fewer than the wave's count if its zone was crowded.
+    pub count: u32,
+    /// Agents of the team still standing when the wave arrived.
+    pub team_standing: u32,
+    /// Agents of this arrival alive at the end.
+    pub survivors: u32,
+}
+
+/// How one team fared in a battle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TeamResult {
+    pub name: String,
+    /// Each profile in the team, in spawn order.
+    pub members: Vec<MemberResult>,
+    pub tactics: Tactics,
+    /// Teams sharing an alliance number fought on the same side.
+    pub alliance: usize,
+    pub count: u32,
+    pub survivors: u32,
+    pub damage_dealt: u32,
+    pub formation: Formation,
+    pub won: bool,
+    /// Average and highest `Agent::power` among the team's individuals at the start.
+    #[serde(default)]
+    pub mean_power: f64,
+    #[serde(default)]
+    pub top_power: f64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BattleResult {
     pub battle_id: usize,
-    pub winner: Team,
+    /// Names of the winning alliance's teams joined by " & ", or "Draw".
+    pub winner: String,
     pub rounds: u32,
+    /// Deaths across every team fighting with group tactics.
     pub group_casualties: u32,
+    /// Whether any agent fighting with solo tactics lived.
     pub solo_survived: bool,
     pub context: BattleContext,
     pub causal: CausalMetrics,
+    #[serde(default)]
+    pub round_mode: RoundMode,
+    #[serde(default)]
+    pub engine: Engine,
+    /// Simulated time the battle lasted.
+    #[serde(default)]
+    pub elapsed_seconds: f64,
+    /// Terrain map the battle was fought on.
+    #[serde(default)]
+    pub map: String,
+    /// Agents per usable arena cell at the start of the battle.
+    #[serde(default)]
+    pub density: f64,
+    /// Formations of the first group-tactics and solo-tactics teams.
+    #[serde(default)]
+    pub group_formation: Formation,
+    #[serde(default)]
+    pub solo_formation: Formation,
+    /// Weather over the battle, starting with `context.weather`.
+    #[serde(default)]
+    pub weather_timeline: Vec<WeatherChange>,
+    #[serde(default)]
+    pub teams: Vec<TeamResult>,
+    /// Reinforcements in the order they arrived.
+    #[serde(default)]
+    pub arrivals: Vec<Arrival>,
+    /// Every agent that fought with solo tactics, in spawn order.
+    #[serde(default)]
+    pub solos: Vec<SoloResult>,
+    #[serde(default)]
+    pub solos_alive: u32,
+    /// Seed the individuals' stats were drawn from.
+    #[serde(default)]
+    pub seed: u64,
 }
@@ -1,4 +1,5 @@
-use crate::models::{BattleResult, Team};
+use crate::models::{BattleResult, DRAW, TIMEOUT};
+use std::collections::BTreeMap;
 use serde_json;
 use std::fs::File;
 use std::path::Path;
@@ -63,19 +64,32 @@ pub fn export_results(results: &[BattleResult], batch_id: usize) {
 
 pub fn print_results(results: &[BattleResult]) {
     let total_battles = results.len();
-    let group_wins = results.iter().filter(|r| r.winner == Team::Group).count();
-    let solo_wins = results.iter().filter(|r| r.winner == Team::Solo).count();
+    let mut wins: BTreeMap<&str, usize> = BTreeMap::new();
+    for r in results {
+        *wins.entry(&r.winner).or_insert(0) += 1;
+    }
     let avg_rounds = results.iter().map(|r| r.rounds as f64).sum::<f64>() / total_battles as f64;
     let avg_casualties = results.iter().map(|r| r.group_casualties as f64).sum::<f64>() / total_battles as f64;
     let solo_survival_rate = results.iter().filter(|r| r.solo_survived).count() as f64 / total_battles as f64;
 
     println!("\nSimulation Results:");
     println!("Total Battles: {}", total_battles);
-    println!("Group Wins: {} ({:.1}%)", group_wins, (group_wins as f64 / total_battles as f64) * 100.0);
-    println!("Solo Wins: {} ({:.1}%)", solo_wins, (solo_wins as f64 / total_battles as f64) * 100.0);
+    for (winner, count) in &wins {
+        let label = match *winner {
+            DRAW => "Draws".to_string(),
+            TIMEOUT => "Timeouts".to_string(),
+            _ => format!("{} Wins", winner),
+        };
+        println!("{}: {} ({:.1}%)", label, count, (*count as f64 / total_battles as f64) * 100.0);
+    }
     println!("Average Rounds: {:.1}", avg_rounds);
     println!("Average Group Casualties: {:.1}", avg_casualties);
     println!("Solo Survival Rate: {:.1}%", solo_survival_rate * 100.0);
+    if results.iter().any(|r| r.solos.len() > 1) {
+        let solos = results.iter().map(|r| r.solos.len()).sum::<usize>() as f64 / total_battles as f64;
+        let alive = results.iter().map(|r| r.solos_alive).sum::<u32>() as f64 / total_battles as f64;
+        println!("Average Solos Alive: {:.2} of {:.2}", alive, solos);
+    }
 
     println!("\nBattle Context:");
     if let Some(result) = results.first() {
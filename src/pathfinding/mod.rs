@@ -0,0 +1,132 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Upper bound on cells expanded per search, so a walled-in goal can't stall a round.
+pub const MAX_EXPANDED: usize = 1500;
+
+/// Expansion allowance per cell of straight-line distance. Keeps searches cheap
+/// for agents pressed up against a crowd around their target.
+const EXPANDED_PER_CELL: usize = 40;
+
+/// Replan once the goal has drifted further than this from the one the cached path was built for.
+pub const REPLAN_DRIFT: i32 = 2;
+
+const NEIGHBOURS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+fn manhattan(a: (i32, i32), b: (i32, i32)) -> i32 {
+    (a.0 - b.0).abs() + (a.1 - b.1).abs()
+}
+
+/// A* search from `start` towards `goal` over the 4-connected grid.
+///
+/// `step_cost` gives the cost of moving from one cell into a neighbouring
+/// one, or `None` if the move isn't possible, including off the edge of the
+/// arena. Cells in `blocked` are skipped except for the goal itself, which is
+/// usually the cell the target stands on. When the goal can't be reached within
+/// the expansion budget the path leads to the closest cell found instead.
+///
+/// The returned path excludes `start` and is stored in reverse, so the next
+/// step is at the end and can be taken with `pop`.
+pub fn find_path<F>(start: (i32, i32), goal: (i32, i32), blocked: &HashSet<(i32, i32)>, step_cost: F) -> Vec<(i32, i32)>
+where
+    F: Fn((i32, i32), (i32, i32)) -> Option<i32>,
+{
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+    let mut cost_so_far: HashMap<(i32, i32), i32> = HashMap::new();
+    let mut closest = (manhattan(start, goal), start);
+    let mut expanded = 0;
+    let budget = (EXPANDED_PER_CELL * (manhattan(start, goal) as usize + 1)).min(MAX_EXPANDED);
+
+    open.push(Reverse((manhattan(start, goal), 0, start)));
+    cost_so_far.insert(start, 0);
+
+    while let Some(Reverse((_, cost, cell))) = open.pop() {
+        if cell == goal {
+            closest = (0, cell);
+            break;
+        }
+        if cost > cost_so_far[&cell] {
+            continue;
+        }
+        expanded += 1;
+        if expanded > budget {
+            break;
+        }
+
+        for (dx, dy) in NEIGHBOURS {
+            let next = (cell.0 + dx, cell.1 + dy);
+            if next != goal && blocked.contains(&next) {
+                continue;
+            }
+            let Some(step) = step_cost(cell, next) else {
+                continue;
+            };
+            let new_cost = cost + step;
+            if cost_so_far.get(&next).is_none_or(|&c| new_cost < c) {
+                cost_so_far.insert(next, new_cost);
+                came_from.insert(next, cell);
+                let h = manhattan(next, goal);
+                if (h, next) < closest {
+                    closest = (h, next);
+                }
+                open.push(Reverse((new_cost + h, new_cost, next)));
+            }
+        }
+    }
+
+    let mut path = Vec::new();
+    let mut cell = closest.1;
+    while cell != start {
+        path.push(cell);
+        cell = came_from[&cell];
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Moves within a `size` x `size` grid cost 1, except into `walls`.
+    fn grid(size: i32, walls: &[(i32, i32)]) -> impl Fn((i32, i32), (i32, i32)) -> Option<i32> + '_ {
+        move |_, (x, y)| {
+            let inside = (0..size).contains(&x) && (0..size).contains(&y);
+            (inside && !walls.contains(&(x, y))).then_some(1)
+        }
+    }
+
+    #[test]
+    fn open_ground_takes_a_shortest_path() {
+        let path = find_path((0, 0), (4, 3), &HashSet::new(), grid(10, &[]));
+        assert_eq!(path.len(), 7);
+        assert_eq!(path.first(), Some(&(4, 3)));
+        assert_eq!(manhattan(*path.last().unwrap(), (0, 0)), 1);
+    }
+
+    #[test]
+    fn goes_around_blocked_cells_but_not_the_goal() {
+        // A line of agents across x = 2 with a gap at y = 4, and one on the goal
+        let mut blocked: HashSet<(i32, i32)> = (0..4).map(|y| (2, y)).collect();
+        blocked.insert((4, 0));
+        let path = find_path((0, 0), (4, 0), &blocked, grid(5, &[]));
+        assert_eq!(path.first(), Some(&(4, 0)));
+        assert!(path[1..].iter().all(|cell| !blocked.contains(cell)));
+        assert!(path.contains(&(2, 4)));
+        assert_eq!(path.len(), 12);
+    }
+
+    #[test]
+    fn unreachable_goal_leads_to_the_closest_cell() {
+        let walls = [(4, 5), (6, 5), (5, 4), (5, 6)];
+        let path = find_path((0, 5), (5, 5), &HashSet::new(), grid(10, &walls));
+        assert!(!path.contains(&(5, 5)));
+        assert_eq!(path.first(), Some(&(3, 5)));
+        assert_eq!(path.len(), 3);
+    }
+
+    #[test]
+    fn no_path_from_the_goal_itself() {
+        assert!(find_path((2, 2), (2, 2), &HashSet::new(), grid(5, &[])).is_empty());
+    }
+}
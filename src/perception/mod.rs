@@ -0,0 +1,122 @@
+use crate::environment::EnvironmentModifiers;
+use crate::models::Agent;
+
+/// Agents always notice enemies this close, whatever the conditions.
+pub const CONTACT_RANGE: i32 = 1;
+
+/// How far an agent can see in clear daylight, in cells.
+pub fn base_sight_radius(int_environmental: i32) -> i32 {
+    4 + int_environmental * 2
+}
+
+/// Sight radius for an agent with the given `int_environmental` under the
+/// battle's visibility modifier.
+pub fn sight_radius(int_environmental: i32, modifiers: &EnvironmentModifiers) -> i32 {
+    let radius = base_sight_radius(int_environmental) as f32 * modifiers.visibility;
+    (radius.round() as i32).max(CONTACT_RANGE)
+}
+
+/// Allies this close can call out the enemies they see to each other.
+pub const CALL_RANGE: i32 = 10;
+
+/// Refreshes every living agent's memory of enemy positions, then lets those
+/// who know of no enemy hear about the ones their allies see.
+pub fn update(agents: &mut [Agent]) {
+    for i in 0..agents.len() {
+        observe(agents, i);
+    }
+    for i in 0..agents.len() {
+        hear(agents, i);
+    }
+}
+
+/// Refreshes agent `i`'s memory of enemy positions. Enemies in sight are
+/// (re)recorded; remembered spots that are now in sight but empty, or hold a
+/// body, are forgotten.
+pub fn observe(agents: &mut [Agent], i: usize) {
+    if !agents[i].alive {
+        return;
+    }
+
+    let observer = &agents[i];
+    let mut seen = Vec::new();
+    let mut lost = Vec::new();
+    for (j, other) in agents.iter().enumerate() {
+        if !other.is_enemy_of(observer) {
+            continue;
+        }
+        let visible = observer.distance_to(other) <= observer.sight_radius;
+        if visible && other.alive {
+            seen.push((j, (other.x, other.y)));
+        } else if let Some(&(mx, my)) = observer.memory.get(&j) {
+            let spot_in_sight = (observer.x - mx).abs() + (observer.y - my).abs() <= observer.sight_radius;
+            if visible || spot_in_sight {
+                lost.push(j);
+            }
+        }
+    }
+
+    let observer = &mut agents[i];
+    for j in lost {
+        observer.memory.remove(&j);
+    }
+    observer.memory.extend(seen);
+}
+
+/// An agent that knows of no enemy learns where living allies within
+/// `CALL_RANGE` see them standing.
+pub fn hear(agents: &mut [Agent], i: usize) {
+    let listener = &agents[i];
+    if !listener.alive || !listener.memory.is_empty() {
+        return;
+    }
+
+    let callers = agents
+        .iter()
+        .filter(|ally| ally.alive && ally.id != listener.id && !ally.is_enemy_of(listener))
+        .filter(|ally| listener.distance_to(ally) <= CALL_RANGE);
+    // Only what a caller sees now, so nobody is sent to a spot already found empty
+    let mut heard = Vec::new();
+    for caller in callers {
+        let in_sight = caller
+            .memory
+            .keys()
+            .map(|&j| (j, &agents[j]))
+            .filter(|(_, enemy)| enemy.alive && caller.distance_to(enemy) <= caller.sight_radius);
+        heard.extend(in_sight.map(|(j, enemy)| (j, (enemy.x, enemy.y))));
+    }
+    agents[i].memory.extend(heard);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(id: usize, alliance: usize, x: i32, sight_radius: i32) -> Agent {
+        Agent { id, alliance, team: alliance, x, sight_radius, ..Agent::default() }
+    }
+
+    #[test]
+    fn allies_in_call_range_hear_what_others_see() {
+        let mut agents = vec![
+            agent(0, 0, 0, 5),
+            agent(1, 0, CALL_RANGE, 5),
+            agent(2, 0, -CALL_RANGE - 1, 5),
+            agent(3, 1, 3, 5),
+        ];
+        update(&mut agents);
+        assert_eq!(agents[0].memory.get(&3), Some(&(3, 0)));
+        assert_eq!(agents[1].memory.get(&3), Some(&(3, 0)));
+        assert!(agents[2].memory.is_empty());
+        assert!(agents[3].memory.contains_key(&0));
+    }
+
+    #[test]
+    fn callers_only_pass_on_enemies_they_still_see() {
+        let mut agents = vec![agent(0, 0, 0, 2), agent(1, 0, 5, 2), agent(2, 1, 20, 2)];
+        // Agent 0 remembers the enemy from earlier but has lost sight of it
+        agents[0].memory.insert(2, (1, 0));
+        hear(&mut agents, 1);
+        assert!(agents[1].memory.is_empty());
+    }
+}
@@ -0,0 +1,359 @@
+use crate::arena::ArenaConfig;
+use crate::deployment::{Deployment, Formation, SideDeployment};
+use crate::environment::LocationConfig;
+use crate::models::Tactics;
+use crate::solar::{parse_date, parse_time};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufReader;
+
+/// How agents' turns within a round are resolved.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum RoundMode {
+    /// Agents act one after another in a shuffled order.
+    #[default]
+    Sequential,
+    /// Agents all decide from the start-of-round state and act at once.
+    Simultaneous,
+}
+
+/// Which engine advances the battle clock.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum Engine {
+    /// Fixed rounds in which every agent acts once.
+    #[default]
+    Rounds,
+    /// Event queue where each action takes time scaled by speed and fatigue.
+    DiscreteEvent,
+}
+
+/// Some number of agents of one profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeamMember {
+    pub profile: String,
+    pub count: usize,
+}
+
+/// One named team in a battle, made up of one profile (`profile` and `count`)
+/// or a mix (`members`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TeamConfig {
+    pub name: String,
+    #[serde(default)]
+    pub profile: String,
+    #[serde(default)]
+    pub count: usize,
+    /// Listed in spawn order; earlier entries take the cells a formation fills first.
+    #[serde(default)]
+    pub members: Vec<TeamMember>,
+    #[serde(default)]
+    pub tactics: Tactics,
+    /// Spawn zone and formation.
+    #[serde(flatten)]
+    pub side: SideDeployment,
+}
+
+impl TeamConfig {
+    /// Every profile in the team with its count.
+    pub fn members(&self) -> Vec<TeamMember> {
+        if self.members.is_empty() {
+            vec![TeamMember {
+                profile: self.profile.clone(),
+                count: self.count,
+            }]
+        } else {
+            self.members.clone()
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.members().iter().map(|m| m.count).sum()
+    }
+}
+
+/// Reinforcements for a team: `count` agents of `profile` that arrive in
+/// round `round`, and again every `every` rounds if set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Wave {
+    pub team: String,
+    pub profile: String,
+    pub count: usize,
+    pub round: u32,
+    #[serde(default)]
+    pub every: Option<u32>,
+    /// Arrivals in all when the wave repeats. Without it the wave keeps
+    /// coming for as long as the battle goes on.
+    #[serde(default)]
+    pub times: Option<u32>,
+    /// Spawn zone and formation of each arrival.
+    #[serde(flatten)]
+    pub side: SideDeployment,
+}
+
+impl Wave {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.profile.is_empty() || self.count == 0 {
+            return Err(format!("wave for {} needs a profile and a count above 0", self.team));
+        }
+        if self.round == 0 {
+            return Err(format!("wave for {} can't arrive before round 1", self.team));
+        }
+        if self.every == Some(0) || self.times == Some(0) {
+            return Err(format!("wave for {}: every and times must be above 0", self.team));
+        }
+        if self.times.is_some() && self.every.is_none() {
+            return Err(format!("wave for {} sets times without every", self.team));
+        }
+        self.side.zone.validate()
+    }
+
+    /// Whether an arrival falls in `round`.
+    pub fn arrives_in(&self, round: u32) -> bool {
+        let Some(since) = round.checked_sub(self.round) else {
+            return false;
+        };
+        match self.every {
+            None => since == 0,
+            Some(every) => since % every == 0 && self.times.is_none_or(|times| since / every < times),
+        }
+    }
+
+    /// Whether an arrival is still to come after `round`. Waves that repeat
+    /// without a limit never run out.
+    pub fn due_after(&self, round: u32) -> bool {
+        match (self.every, self.times) {
+            (None, _) => round < self.round,
+            (Some(_), None) => true,
+            (Some(every), Some(times)) => round < self.round + every * (times - 1),
+        }
+    }
+
+    /// Whether every arrival is scheduled in advance.
+    pub fn is_limited(&self) -> bool {
+        self.every.is_none() || self.times.is_some()
+    }
+}
+
+/// Everything needed to run a batch of battles. Fields missing from a
+/// scenario file fall back to the classic 100 Man vs 1 Gorilla setup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scenario {
+    pub group_profile: String,
+    pub solo_profile: String,
+    pub group_count: usize,
+    pub solo_count: usize,
+    /// Members per group squad. 0 disables squads and the group fights as a mob.
+    pub squad_size: usize,
+    pub round_mode: RoundMode,
+    pub engine: Engine,
+    /// Battle date as "MM-DD" or "YYYY-MM-DD". Sampled over the year if unset.
+    pub date: Option<String>,
+    /// Local solar time as "HH:MM". Sampled over the day if unset.
+    pub local_time: Option<String>,
+    /// Terrain map file. The arena is open ground if unset.
+    pub map: Option<String>,
+    /// Arena size and shape. A map sets the size itself.
+    pub arena: ArenaConfig,
+    /// Spawn zones, formations and starting separation for both sides.
+    pub deployment: Deployment,
+    /// Which cities battles are fought in and how they are sampled.
+    pub location: LocationConfig,
+    /// Teams taking part. When empty, the group and solo fields describe two
+    /// teams named "Group" and "Solo".
+    pub teams: Vec<TeamConfig>,
+    /// Teams listed together fight on the same side. Teams left out fight alone.
+    pub alliances: Vec<Vec<String>>,
+    /// Agents joining teams after the battle has started.
+    pub reinforcements: Vec<Wave>,
+    /// Makes the individuals drawn for each battle repeatable. Random if unset.
+    pub seed: Option<u64>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario {
+            group_profile: "Man".to_string(),
+            solo_profile: "Gorilla".to_string(),
+            group_count: 100,
+            solo_count: 1,
+            squad_size: 0,
+            round_mode: RoundMode::Sequential,
+            engine: Engine::Rounds,
+            date: None,
+            local_time: None,
+            map: None,
+            arena: ArenaConfig::default(),
+            deployment: Deployment::default(),
+            location: LocationConfig::default(),
+            teams: Vec::new(),
+            alliances: Vec::new(),
+            reinforcements: Vec::new(),
+            seed: None,
+        }
+    }
+}
+
+impl Scenario {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Could not open scenario {}: {}", path, e))?;
+        let reader = BufReader::new(file);
+        let scenario: Scenario =
+            serde_json::from_reader(reader).map_err(|e| format!("Failed to deserialize scenario {}: {}", path, e))?;
+        scenario.validate().map_err(|e| format!("Invalid scenario {}: {}", path, e))?;
+        Ok(scenario)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.day_of_year()?;
+        self.local_hour()?;
+        self.arena.validate()?;
+        self.deployment.validate()?;
+        self.location.validate()?;
+        if self.map.is_some() && self.arena.density.is_some() {
+            return Err("density can't be used with a map, which has a fixed size".to_string());
+        }
+        self.validate_teams()
+    }
+
+    fn validate_teams(&self) -> Result<(), String> {
+        let teams = self.teams();
+        let mut names = HashSet::new();
+        for team in &teams {
+            if team.name.is_empty() {
+                return Err("every team needs a name".to_string());
+            }
+            if !names.insert(team.name.as_str()) {
+                return Err(format!("team {} is listed twice", team.name));
+            }
+            if !team.members.is_empty() && (!team.profile.is_empty() || team.count > 0) {
+                return Err(format!("team {} sets both members and profile/count", team.name));
+            }
+            if team.members().iter().any(|m| m.profile.is_empty() || m.count == 0) {
+                return Err(format!("team {} needs a profile and a count above 0 for every member", team.name));
+            }
+            team.side.zone.validate()?;
+        }
+        if teams.iter().filter(|t| t.side.formation == Formation::Ring).count() > 1 {
+            return Err("only one team can form a ring around the others".to_string());
+        }
+
+        let mut allied = HashSet::new();
+        for alliance in &self.alliances {
+            for name in alliance {
+                if !names.contains(name.as_str()) {
+                    return Err(format!("alliance names unknown team {}", name));
+                }
+                if !allied.insert(name.as_str()) {
+                    return Err(format!("team {} is in more than one alliance", name));
+                }
+            }
+        }
+        for wave in &self.reinforcements {
+            if !names.contains(wave.team.as_str()) {
+                return Err(format!("wave names unknown team {}", wave.team));
+            }
+            wave.validate()?;
+        }
+        let alliances: HashSet<usize> = self.alliance_numbers(&teams).into_iter().collect();
+        if alliances.len() < 2 {
+            return Err("a battle needs at least two sides that are not allied".to_string());
+        }
+        Ok(())
+    }
+
+    /// The teams in the battle, in spawn order.
+    pub fn teams(&self) -> Vec<TeamConfig> {
+        if !self.teams.is_empty() {
+            return self.teams.clone();
+        }
+        vec![
+            TeamConfig {
+                name: "Group".to_string(),
+                profile: self.group_profile.clone(),
+                count: self.group_count,
+                members: Vec::new(),
+                tactics: Tactics::Group,
+                side: self.deployment.group.clone(),
+            },
+            TeamConfig {
+                name: "Solo".to_string(),
+                profile: self.solo_profile.clone(),
+                count: self.solo_count,
+                members: Vec::new(),
+                tactics: Tactics::Solo,
+                side: self.deployment.solo.clone(),
+            },
+        ]
+    }
+
+    /// Alliance number of each team in `teams`. Allied teams share the number
+    /// of their alliance's first team; teams outside any alliance keep their own index.
+    pub fn alliance_numbers(&self, teams: &[TeamConfig]) -> Vec<usize> {
+        teams
+            .iter()
+            .enumerate()
+            .map(|(i, team)| {
+                self.alliances
+                    .iter()
+                    .find(|alliance| alliance.contains(&team.name))
+                    .and_then(|alliance| teams.iter().position(|t| alliance.contains(&t.name)))
+                    .unwrap_or(i)
+            })
+            .collect()
+    }
+
+    pub fn day_of_year(&self) -> Result<Option<u32>, String> {
+        self.date.as_deref().map(parse_date).transpose()
+    }
+
+    pub fn local_hour(&self) -> Result<Option<f64>, String> {
+        self.local_time.as_deref().map(parse_time).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wave(round: u32, every: Option<u32>, times: Option<u32>) -> Wave {
+        Wave {
+            team: "Group".to_string(),
+            profile: "Man".to_string(),
+            count: 5,
+            round,
+            every,
+            times,
+            side: SideDeployment::default(),
+        }
+    }
+
+    #[test]
+    fn single_wave_arrives_once() {
+        let once = wave(10, None, None);
+        assert!(!once.arrives_in(9));
+        assert!(once.arrives_in(10));
+        assert!(!once.arrives_in(11));
+        assert!(once.due_after(9));
+        assert!(!once.due_after(10));
+    }
+
+    #[test]
+    fn repeating_wave_stops_after_its_times() {
+        // Rounds 5, 8 and 11
+        let repeats = wave(5, Some(3), Some(3));
+        let arrivals: Vec<u32> = (0..30).filter(|&round| repeats.arrives_in(round)).collect();
+        assert_eq!(arrivals, vec![5, 8, 11]);
+        assert!(repeats.due_after(10));
+        assert!(!repeats.due_after(11));
+    }
+
+    #[test]
+    fn unlimited_wave_is_always_due() {
+        let endless = wave(2, Some(4), None);
+        assert!(endless.arrives_in(402));
+        assert!(!endless.arrives_in(403));
+        assert!(endless.due_after(10_000));
+    }
+}
@@ -1,295 +1,1098 @@
 use crate::arena::*;
-use crate::models::{Agent, BattleResult, Team};
+use crate::models::{Agent, Arrival, BattleResult, MemberResult, SoloResult, Tactics, TeamResult, DRAW, TIMEOUT};
 use crate::output::*;
-use crate::character_profiles::{load_profiles, get_profile, AgentProfile};
-use crate::environment::BattleContext;
+use crate::character_profiles::{AgentProfile, ProfileRegistry, PROFILES_FILE};
+use crate::environment::{BattleContext, CityTable, EnvironmentModifiers, LocationSampler, ModifierTable};
 use crate::causal::CausalMetrics;
-use rand::Rng;
+use crate::deployment::{Deployment, Side};
+use crate::events;
+use crate::perception;
+use crate::scenario::{Engine, RoundMode, Scenario, TeamConfig, TeamMember, Wave};
+use crate::squads::{target_for, Command};
+use crate::terrain::Mobility;
+use crate::thermal::{thermal_strain, TemperatureTable};
+use crate::weather::{Conditions, WeatherChains, WeatherDistributions, WEATHER_STEP_SECONDS};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use rand::seq::SliceRandom;
 use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 
-pub fn run_batch_simulation(
-    n: usize,
-    batch_id: usize,
-    group_profile_id: &str,
-    solo_profile_id: &str,
-    group_count: usize,
-    solo_count: usize,
-) {
-    let profiles = load_profiles("combatants.json");
-    let group_profile = get_profile(&profiles, group_profile_id);
-    let solo_profile = get_profile(&profiles, solo_profile_id);
-
-    let results: Vec<BattleResult> = (0..n)
-        .into_par_iter()
-        .map(|id| run_single_simulation(id, group_profile, solo_profile, group_count, solo_count))
-        .collect();
+/// Runs `n` battles and saves them as batch `batch_id`. Fails before any
+/// battle runs if the profiles or the scenario's setup don't work.
+pub fn run_batch_simulation(n: usize, batch_id: usize, scenario: &Scenario) -> Result<(), String> {
+    let profiles = ProfileRegistry::load(PROFILES_FILE)?;
+    let results = simulate_batch(n, 0, scenario, &profiles)?;
+    export_results(&results, batch_id);
+    Ok(())
+}
 
+/// Runs `n` battles at each density in turn, resizing the scenario's arena to
+/// match, and saves them all as one batch.
+pub fn run_density_sweep(n: usize, batch_id: usize, scenario: &Scenario, densities: &[f64]) -> Result<(), String> {
+    let profiles = ProfileRegistry::load(PROFILES_FILE)?;
+    let mut results = Vec::new();
+    for (step, &density) in densities.iter().enumerate() {
+        let mut swept = scenario.clone();
+        swept.arena.density = Some(density);
+        let batch = swept
+            .validate()
+            .and_then(|_| simulate_batch(n, step * n, &swept, &profiles))
+            .map_err(|e| format!("Invalid sweep at density {}: {}", density, e))?;
+        results.extend(batch);
+    }
     export_results(&results, batch_id);
+    Ok(())
+}
+
+/// Runs `n` battles numbered from `first_id`.
+fn simulate_batch(n: usize, first_id: usize, scenario: &Scenario, profiles: &ProfileRegistry) -> Result<Vec<BattleResult>, String> {
+    let teams = team_setups(scenario, profiles)?;
+    let cities = CityTable::load("realistic_cities_with_climate.csv");
+    let tables = BatchTables {
+        modifiers: ModifierTable::load("environment_modifiers.json"),
+        weather_odds: WeatherDistributions::load("weather_distributions.json"),
+        weather_chains: WeatherChains::load("weather_transitions.json"),
+        temperatures: TemperatureTable::load("climate_temperatures.json"),
+        locations: cities
+            .sampler(&scenario.location)
+            .map_err(|e| format!("Invalid location settings: {}", e))?,
+    };
+    let climates = cities.climates();
+    tables
+        .weather_odds
+        .validate(&tables.weather_chains, climates.iter().copied())
+        .map_err(|e| format!("Invalid weather_distributions.json: {}", e))?;
+    if let Some(climate) = tables.temperatures.missing(climates.into_iter()) {
+        return Err(format!("No temperatures for climate {} in climate_temperatures.json", climate));
+    }
+    let agent_count = teams.iter().map(|t| t.config.size()).sum();
+    let arena_config = scenario.arena.sized_for(agent_count);
+    let arena = Arena::build(&arena_config, scenario.map.as_deref()).map_err(|e| format!("Invalid arena: {}", e))?;
+    check_fit(&arena, &teams, &scenario.deployment).map_err(|e| format!("Agents don't fit the arena: {}", e))?;
+
+    (first_id..first_id + n)
+        .into_par_iter()
+        .map(|id| run_single_simulation(id, &teams, scenario, &tables, &arena).map_err(|e| format!("Battle {}: {}", id, e)))
+        .collect()
+}
+
+/// The scenario's teams with their profiles looked up. Members and waves
+/// take the name of the profile they found, so results always use it.
+fn team_setups<'a>(scenario: &Scenario, profiles: &'a ProfileRegistry) -> Result<Vec<TeamSetup<'a>>, String> {
+    let configs = scenario.teams();
+    let alliances = scenario.alliance_numbers(&configs);
+    let mut teams = Vec::new();
+    for (config, alliance) in configs.into_iter().zip(alliances) {
+        let mut members = Vec::new();
+        for mut member in config.members() {
+            let (name, profile) = profiles.find(&member.profile)?;
+            member.profile = name.to_string();
+            members.push((member, profile));
+        }
+        let mut waves = Vec::new();
+        for wave in scenario.reinforcements.iter().filter(|wave| wave.team == config.name) {
+            let (name, profile) = profiles.find(&wave.profile)?;
+            let wave = Wave {
+                profile: name.to_string(),
+                ..wave.clone()
+            };
+            waves.push((wave, profile));
+        }
+        teams.push(TeamSetup {
+            config,
+            members,
+            alliance,
+            waves,
+        });
+    }
+    Ok(teams)
+}
+
+/// A team in a batch with its profiles and alliance resolved.
+pub struct TeamSetup<'a> {
+    pub config: TeamConfig,
+    pub members: Vec<(TeamMember, &'a AgentProfile)>,
+    pub alliance: usize,
+    /// Reinforcements for the team. Their agents are numbered as members
+    /// after the team's own.
+    pub waves: Vec<(Wave, &'a AgentProfile)>,
+}
+
+impl TeamSetup<'_> {
+    /// Where every member of the team can go, for placing it as one formation.
+    fn mobility(&self) -> Mobility {
+        let mobilities: Vec<Mobility> = self.members.iter().map(|(_, profile)| Mobility::from_profile(profile)).collect();
+        Mobility::common(&mobilities)
+    }
+}
+
+/// Placements tried when checking a scenario fits, and again at the start of
+/// each battle before giving up.
+const PLACEMENT_TRIES: u64 = 20;
+
+/// Checks that every team has enough cells in its spawn zone, and that they
+/// all fit together. The teams are also placed `PLACEMENT_TRIES` times to
+/// catch formations that can't keep `min_separation` before any battle runs.
+/// Formations land in different places each battle, so a battle can still
+/// fail to deploy; that fails the batch with an error.
+fn check_fit(arena: &Arena, teams: &[TeamSetup], deployment: &Deployment) -> Result<(), String> {
+    let mut all_cells = HashSet::new();
+    for team in teams {
+        let cells = deployment.zone_cells(arena, &team.config.side, &team.mobility());
+        let count = team.config.size();
+        if count > cells.len() {
+            return Err(format!(
+                "{} {} agents but only {} cells to place them on a {}x{} arena",
+                count, team.config.name, cells.len(), arena.width(), arena.height()
+            ));
+        }
+        all_cells.extend(cells);
+        for (wave, profile) in &team.waves {
+            let cells = deployment.zone_cells(arena, &wave.side, &Mobility::from_profile(profile));
+            if wave.count > cells.len() {
+                return Err(format!(
+                    "{} {} reinforcements for {} but only {} cells in their zone",
+                    wave.count, wave.profile, team.config.name, cells.len()
+                ));
+            }
+        }
+    }
+    let total: usize = teams.iter().map(|t| t.config.size()).sum();
+    if total > all_cells.len() {
+        return Err(format!(
+            "{} agents in all but only {} cells to place them on a {}x{} arena",
+            total, all_cells.len(), arena.width(), arena.height()
+        ));
+    }
+    let mobilities: Vec<Mobility> = teams.iter().map(|t| t.mobility()).collect();
+    let sides = sides(teams, &mobilities);
+    for seed in 0..PLACEMENT_TRIES {
+        deployment.place(arena, &sides, &mut StdRng::seed_from_u64(seed))?;
+    }
+    Ok(())
+}
+
+/// What `Deployment::place` needs to know about each team.
+fn sides<'a>(teams: &'a [TeamSetup], mobilities: &'a [Mobility]) -> Vec<Side<'a>> {
+    teams
+        .iter()
+        .zip(mobilities)
+        .map(|(team, mobility)| Side {
+            deployment: &team.config.side,
+            mobility,
+            count: team.config.size(),
+            alliance: team.alliance,
+        })
+        .collect()
+}
+
+/// Length of a round in simulated seconds.
+pub const SECONDS_PER_ROUND: f64 = 3.0;
+
+/// Battles are cut off after this many rounds.
+pub(crate) const MAX_ROUNDS: u32 = 1000;
+
+/// A battle ends once this many rounds pass without damage while opponents are within reach of each other.
+pub(crate) const STALL_ROUNDS: u32 = 10;
+
+/// A battle also ends once this many rounds pass with no agent aware of any
+/// enemy and no reinforcements on the way.
+pub(crate) const SEARCH_ROUNDS: u32 = 100;
+
+/// Rounds between steps of the weather chain.
+const WEATHER_STEP_ROUNDS: u32 = (WEATHER_STEP_SECONDS / SECONDS_PER_ROUND) as u32;
+
+/// Data files a batch loads once and shares between its battles.
+pub struct BatchTables<'a> {
+    pub modifiers: ModifierTable,
+    pub weather_odds: WeatherDistributions,
+    pub weather_chains: WeatherChains,
+    pub temperatures: TemperatureTable,
+    pub locations: LocationSampler<'a>,
+}
+
+/// Where a battle is fought: the arena's terrain and the conditions in effect.
+pub struct Battlefield<'a> {
+    pub arena: &'a Arena,
+    /// Modifiers for the current weather, kept in step with `conditions`.
+    pub effects: EnvironmentModifiers,
+    pub conditions: Conditions<'a>,
+}
+
+impl Battlefield<'_> {
+    /// Steps the weather at `seconds` into the battle. If it changes, the
+    /// modifiers, every agent's sight radius and their thermal strain follow.
+    pub(crate) fn step_weather(&mut self, agents: &mut [Agent], seconds: f64, rng: &mut impl Rng) {
+        if !self.conditions.step(seconds, rng) {
+            return;
+        }
+        self.effects = self.conditions.applied.combined.clone();
+        for agent in agents.iter_mut() {
+            self.acclimatize(agent);
+        }
+    }
+
+    /// Sets the agent's sight radius and thermal strain for the current conditions.
+    fn acclimatize(&self, agent: &mut Agent) {
+        let temperature = self.conditions.context.temperature_c;
+        agent.sight_radius = perception::sight_radius(agent.int_environmental, &self.effects);
+        agent.thermal_strain = thermal_strain(temperature, agent.endurance, agent.heat_tolerance, agent.cold_tolerance);
+    }
+}
+
+/// Reinforcement waves of one battle: when they arrive and how they fared.
+pub struct Reinforcements<'a> {
+    teams: &'a [TeamSetup<'a>],
+    deployment: &'a Deployment,
+    squad_size: usize,
+    seed: u64,
+    /// Each arrival so far with the indices of its agents.
+    arrived: Vec<(Arrival, Range<usize>)>,
+}
+
+impl<'a> Reinforcements<'a> {
+    fn new(teams: &'a [TeamSetup<'a>], scenario: &'a Scenario, seed: u64) -> Self {
+        Reinforcements {
+            teams,
+            deployment: &scenario.deployment,
+            squad_size: scenario.squad_size,
+            seed,
+            arrived: Vec::new(),
+        }
+    }
+
+    /// Alliances with a wave still to arrive after `round` whose arrivals are
+    /// all scheduled. A side waiting on one hasn't lost yet.
+    fn due_after(&self, round: u32) -> impl Iterator<Item = usize> + '_ {
+        self.teams
+            .iter()
+            .filter(move |team| team.waves.iter().any(|(wave, _)| wave.is_limited() && wave.due_after(round)))
+            .map(|team| team.alliance)
+    }
+
+    /// Brings in every wave arriving in `round` at `seconds` into the battle.
+    /// Returns the indices of the agents that arrived.
+    pub(crate) fn arrive(
+        &mut self,
+        agents: &mut Vec<Agent>,
+        command: &mut Command,
+        round: u32,
+        seconds: f64,
+        field: &Battlefield,
+        rng: &mut impl Rng,
+    ) -> Range<usize> {
+        let first = agents.len();
+        for (t, team) in self.teams.iter().enumerate() {
+            for (w, (wave, profile)) in team.waves.iter().enumerate() {
+                if !wave.arrives_in(round) {
+                    continue;
+                }
+                let mobility = Mobility::from_profile(profile);
+                let side = Side {
+                    deployment: &wave.side,
+                    mobility: &mobility,
+                    count: wave.count,
+                    alliance: team.alliance,
+                };
+                let taken = get_occupied_positions(agents);
+                let enemies: Vec<(i32, i32)> = agents
+                    .iter()
+                    .filter(|a| a.alive && a.alliance != team.alliance)
+                    .map(|a| (a.x, a.y))
+                    .collect();
+                let team_standing = agents.iter().filter(|a| a.alive && a.team == t).count() as u32;
+
+                let start = agents.len();
+                for (x, y) in self.deployment.reinforce(field.arena, &side, &taken, &enemies, rng) {
+                    let individual = profile.individual(&mut StdRng::seed_from_u64(derive_seed(self.seed, agents.len())));
+                    let mut agent = Agent::new_from_profile(agents.len(), t, team.config.tactics, x, y, &individual);
+                    agent.member = team.members.len() + w;
+                    agent.alliance = team.alliance;
+                    field.acclimatize(&mut agent);
+                    agents.push(agent);
+                }
+                let new = start..agents.len();
+                if team.config.tactics == Tactics::Group {
+                    command.add_squads(agents, &new.clone().collect::<Vec<usize>>(), self.squad_size);
+                }
+                if self.deployment.start_aware {
+                    reveal_enemies(agents, new.clone());
+                }
+                self.arrived.push((
+                    Arrival {
+                        team: team.config.name.clone(),
+                        profile: wave.profile.clone(),
+                        round,
+                        seconds,
+                        count: new.len() as u32,
+                        team_standing,
+                        survivors: 0,
+                    },
+                    new,
+                ));
+            }
+        }
+        first..agents.len()
+    }
+
+    /// Every arrival with its survivors counted.
+    fn arrivals(&self, agents: &[Agent]) -> Vec<Arrival> {
+        self.arrived
+            .iter()
+            .map(|(arrival, range)| Arrival {
+                survivors: agents[range.clone()].iter().filter(|a| a.alive).count() as u32,
+                ..arrival.clone()
+            })
+            .collect()
+    }
 }
 
 pub fn run_single_simulation(
     battle_id: usize,
-    group_profile: &AgentProfile,
-    solo_profile: &AgentProfile,
-    group_count: usize,
-    solo_count: usize,
-) -> BattleResult {
-    let mut agents = init_agents(group_profile, solo_profile, group_count, solo_count);
-    let mut round_count = 0;
+    teams: &[TeamSetup],
+    scenario: &Scenario,
+    tables: &BatchTables,
+    arena: &Arena,
+) -> Result<BattleResult, String> {
+    let mut rng = rand::thread_rng();
+    let seed = scenario.seed.map_or_else(|| rng.gen(), |seed| derive_seed(seed, battle_id));
+    let context = BattleContext::at(
+        tables.locations.pick(battle_id, &mut rng),
+        &tables.weather_odds,
+        &tables.temperatures,
+        scenario.day_of_year().expect("Invalid scenario date"),
+        scenario.local_hour().expect("Invalid scenario time"),
+        &mut rng,
+    );
+    let conditions = Conditions::new(&tables.weather_chains, &tables.modifiers, &tables.temperatures, context.clone());
+    let applied = conditions.applied.clone();
+    let effects = &applied.combined;
+    let mut field = Battlefield {
+        arena,
+        effects: effects.clone(),
+        conditions,
+    };
+    let mut agents = init_agents(teams, scenario, arena, seed)?;
+    for agent in agents.iter_mut() {
+        field.acclimatize(agent);
+    }
+    let group_teams: Vec<usize> = (0..teams.len()).filter(|&t| teams[t].config.tactics == Tactics::Group).collect();
+    let mut command = Command::form_squads(&mut agents, &group_teams, scenario.squad_size);
     let mut causal = CausalMetrics::new();
+    causal.start_separation = start_separation(&agents);
+    let mut reinforcements = Reinforcements::new(teams, scenario, seed);
+
+    let (round_count, elapsed_seconds) = match scenario.engine {
+        Engine::Rounds => run_rounds(
+            &mut agents,
+            &mut command,
+            &mut causal,
+            &scenario.round_mode,
+            &mut field,
+            &mut reinforcements,
+        ),
+        Engine::DiscreteEvent => {
+            events::run_events(&mut agents, &mut command, &mut causal, &mut field, &mut reinforcements)
+        }
+    };
+
+    causal.squads = command.squads.len() as u32;
+    causal.leaders_killed = command.leaders_killed(&agents);
+    causal.orders_issued = command.orders_issued;
+    if let Some(profile) = first_profile(teams, Tactics::Group) {
+        causal.group_sight_radius = perception::sight_radius(profile.int_environmental.value(), effects) as u32;
+        causal.group_final_sight_radius =
+            perception::sight_radius(profile.int_environmental.value(), &field.effects) as u32;
+        causal.group_thermal_strain = profile_strain(profile, context.temperature_c);
+    }
+    if let Some(profile) = first_profile(teams, Tactics::Solo) {
+        causal.solo_sight_radius = perception::sight_radius(profile.int_environmental.value(), effects) as u32;
+        causal.solo_final_sight_radius =
+            perception::sight_radius(profile.int_environmental.value(), &field.effects) as u32;
+        causal.solo_thermal_strain = profile_strain(profile, context.temperature_c);
+    }
+    causal.environment = applied;
+    causal.final_environment = field.conditions.applied.clone();
+    causal.slips_per_agent = causal.slips as f32 / agents.len().max(1) as f32;
+
+    let mut result = summarize_battle(battle_id, &agents, teams, round_count, context, causal, scenario);
+    for solo in result.solos.iter_mut() {
+        let agent = &agents[solo.id];
+        solo.end_elevation = arena.elevation_at((agent.x, agent.y)) as u32;
+    }
+    result.causal.solo_end_elevation = result.solos.first().map_or(0, |s| s.end_elevation);
+    result.elapsed_seconds = elapsed_seconds;
+    result.map = arena.name().to_string();
+    result.density = agents.len() as f64 / arena.usable_cells() as f64;
+    result.weather_timeline = field.conditions.timeline;
+    result.arrivals = reinforcements.arrivals(&agents);
+    result.seed = seed;
+    Ok(result)
+}
+
+/// The `n`th seed derived from `seed`, for battles within a batch and
+/// individuals within a battle. Mixed with the SplitMix64 finalizer so
+/// neighbouring seeds don't give overlapping streams.
+fn derive_seed(seed: u64, n: usize) -> u64 {
+    let mut z = seed.wrapping_add((n as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Closest distance between opponents.
+fn start_separation(agents: &[Agent]) -> u32 {
+    agents
+        .iter()
+        .flat_map(|a| agents.iter().filter(|b| b.is_enemy_of(a)).map(move |b| a.distance_to(b)))
+        .min()
+        .unwrap_or(0) as u32
+}
+
+/// First profile of the first team fighting with `tactics`, if there is one.
+fn first_profile<'a>(teams: &[TeamSetup<'a>], tactics: Tactics) -> Option<&'a AgentProfile> {
+    teams.iter().find(|t| t.config.tactics == tactics).map(|t| t.members[0].1)
+}
+
+fn profile_strain(profile: &AgentProfile, temperature: f64) -> f32 {
+    thermal_strain(
+        temperature,
+        profile.endurance.value(),
+        profile.heat_tolerance.value(),
+        profile.cold_tolerance.value(),
+    )
+}
+
+/// Fixed-step engine: every living agent gets one action per round. Returns
+/// the rounds fought and the simulated seconds they took.
+fn run_rounds(
+    agents: &mut Vec<Agent>,
+    command: &mut Command,
+    causal: &mut CausalMetrics,
+    mode: &RoundMode,
+    field: &mut Battlefield,
+    reinforcements: &mut Reinforcements,
+) -> (u32, f64) {
+    let mut rng = rand::thread_rng();
+    let mut round_count = 0;
     let mut last_attacker_id: Option<usize> = None;
     let mut consecutive_no_damage = 0;
+    let mut rounds_searching = 0;
 
-    while simulation_active(&agents) && round_count < 1000 {
+    while simulation_active(agents, reinforcements, round_count) && round_count < MAX_ROUNDS {
+        let seconds = round_count as f64 * SECONDS_PER_ROUND;
+        if round_count > 0 && round_count % WEATHER_STEP_ROUNDS == 0 {
+            field.step_weather(agents, seconds, &mut rng);
+        }
         round_count += 1;
-        let round_damage = execute_round(&mut agents, &mut causal, &mut last_attacker_id);
+        reinforcements.arrive(agents, command, round_count, seconds, field, &mut rng);
+        perception::update(agents);
+        command.issue_orders(agents, round_count);
+        let round_damage = execute_round(agents, command, causal, &mut last_attacker_id, mode, field);
+        debug_assert_eq!(
+            get_occupied_positions(agents).len(),
+            agents.iter().filter(|a| a.alive).count(),
+            "two living agents share a cell"
+        );
         
         if round_damage > 0 {
             causal.rounds_engaged += 1;
             consecutive_no_damage = 0;
-        } else {
+        } else if in_contact(agents) {
             consecutive_no_damage += 1;
-            // If no damage has been dealt for 10 consecutive rounds, end the battle
-            if consecutive_no_damage >= 10 {
+            // If no damage has been dealt for 10 consecutive rounds, end the battle.
+            // Rounds spent searching for or closing on the enemy don't count.
+            if consecutive_no_damage >= STALL_ROUNDS {
                 break;
             }
         }
+        if lost_contact(agents, reinforcements, round_count, &mut rounds_searching) {
+            break;
+        }
     }
 
-    summarize_battle(battle_id, &agents, round_count, causal)
+    (round_count, round_count as f64 * SECONDS_PER_ROUND)
 }
 
-fn init_agents(
-    group_profile: &AgentProfile,
-    solo_profile: &AgentProfile,
-    group_count: usize,
-    solo_count: usize,
-) -> Vec<Agent> {
+/// Each agent draws its stats from its own stream of `seed`, so its stats
+/// depend only on the seed and its place in the spawn order.
+fn init_agents(teams: &[TeamSetup], scenario: &Scenario, arena: &Arena, seed: u64) -> Result<Vec<Agent>, String> {
     let mut agents = Vec::new();
     let mut rng = rand::thread_rng();
 
-    let mut positions: Vec<(i32, i32)> = (0..ARENA_WIDTH)
-        .flat_map(|x| (0..ARENA_HEIGHT).map(move |y| (x, y)))
-        .collect();
-    positions.shuffle(&mut rng);
+    let mobilities: Vec<Mobility> = teams.iter().map(|t| t.mobility()).collect();
+    let sides = sides(teams, &mobilities);
+    // A formation can crowd out the enemy's, so try again with a fresh layout
+    let mut attempt = scenario.deployment.place(arena, &sides, &mut rng);
+    for _ in 1..PLACEMENT_TRIES {
+        if attempt.is_ok() {
+            break;
+        }
+        attempt = scenario.deployment.place(arena, &sides, &mut rng);
+    }
+    let positions = attempt.map_err(|e| format!("could not deploy agents: {}", e))?;
+
+    for (t, (team, cells)) in teams.iter().zip(positions).enumerate() {
+        // Cells come best first, so members listed first get the front of the formation
+        let profiles = team
+            .members
+            .iter()
+            .enumerate()
+            .flat_map(|(m, (member, profile))| std::iter::repeat_n((m, *profile), member.count));
+        for ((x, y), (m, profile)) in cells.into_iter().zip(profiles) {
+            let individual = profile.individual(&mut StdRng::seed_from_u64(derive_seed(seed, agents.len())));
+            let mut agent = Agent::new_from_profile(agents.len(), t, team.config.tactics, x, y, &individual);
+            agent.member = m;
+            agent.alliance = team.alliance;
+            agents.push(agent);
+        }
+    }
 
-    for i in 0..group_count {
-        let (x, y) = positions.pop().unwrap();
-        agents.push(Agent::new_from_profile(i, Team::Group, x, y, group_profile));
+    if scenario.deployment.start_aware {
+        let everyone = 0..agents.len();
+        reveal_enemies(&mut agents, everyone);
     }
-    for j in 0..solo_count {
-        let id = group_count + j;
-        let (x, y) = positions.pop().unwrap();
-        agents.push(Agent::new_from_profile(id, Team::Solo, x, y, solo_profile));
+
+    Ok(agents)
+}
+
+/// Tells the agents in `who` where every living enemy stands.
+fn reveal_enemies(agents: &mut [Agent], who: Range<usize>) {
+    let positions: Vec<(usize, (i32, i32), bool)> =
+        agents.iter().map(|a| (a.alliance, (a.x, a.y), a.alive)).collect();
+    for agent in &mut agents[who] {
+        let enemies = positions.iter().enumerate().filter(|(_, &(alliance, _, alive))| alive && alliance != agent.alliance);
+        let known: Vec<(usize, (i32, i32))> = enemies.map(|(j, &(_, position, _))| (j, position)).collect();
+        agent.memory.extend(known);
     }
+}
 
-    agents
+/// Whether any two living opponents stand next to each other.
+pub(crate) fn in_contact(agents: &[Agent]) -> bool {
+    let living: Vec<&Agent> = agents.iter().filter(|a| a.alive).collect();
+    living.iter().enumerate().any(|(n, a)| {
+        living[n + 1..]
+            .iter()
+            .any(|b| b.is_enemy_of(a) && a.distance_to(b) <= perception::CONTACT_RANGE)
+    })
 }
 
-fn simulation_active(agents: &Vec<Agent>) -> bool {
-    let group_alive = agents.iter().any(|a| a.team == Team::Group && a.alive);
-    let solo_alive = agents.iter().any(|a| a.team == Team::Solo && a.alive);
-    group_alive && solo_alive
+/// Counts the rounds in a row that no living agent knows of an enemy, and
+/// whether there have been `SEARCH_ROUNDS` of them with no scheduled
+/// reinforcements still to come after `round`.
+pub(crate) fn lost_contact(agents: &[Agent], reinforcements: &Reinforcements, round: u32, rounds_searching: &mut u32) -> bool {
+    if agents.iter().any(|a| a.alive && !a.memory.is_empty()) {
+        *rounds_searching = 0;
+        return false;
+    }
+    *rounds_searching += 1;
+    *rounds_searching >= SEARCH_ROUNDS && reinforcements.due_after(round).next().is_none()
 }
 
-fn execute_round(agents: &mut Vec<Agent>, causal: &mut CausalMetrics, last_attacker_id: &mut Option<usize>) -> i32 {
+/// The battle goes on after `round` while more than one alliance has agents
+/// standing or scheduled reinforcements still to come.
+pub(crate) fn simulation_active(agents: &[Agent], reinforcements: &Reinforcements, round: u32) -> bool {
+    let mut sides: HashSet<usize> = agents.iter().filter(|a| a.alive).map(|a| a.alliance).collect();
+    sides.extend(reinforcements.due_after(round));
+    sides.len() > 1
+}
+
+/// What an agent does with its turn.
+pub(crate) enum Action {
+    Hold,
+    /// Lost the turn to slippery or treacherous conditions.
+    Slip,
+    /// Sweep the arena for enemies, knowing of none.
+    Search,
+    Move(i32, i32),
+    /// A damage of 0 is a miss.
+    Attack { target: usize, damage: i32, crit: bool },
+}
+
+fn execute_round(
+    agents: &mut [Agent],
+    command: &Command,
+    causal: &mut CausalMetrics,
+    last_attacker_id: &mut Option<usize>,
+    mode: &RoundMode,
+    field: &Battlefield,
+) -> i32 {
+    match mode {
+        RoundMode::Sequential => execute_sequential_round(agents, command, causal, last_attacker_id, field),
+        RoundMode::Simultaneous => execute_simultaneous_round(agents, command, causal, last_attacker_id, field),
+    }
+}
+
+/// Agents act one at a time in a shuffled order, each seeing the moves and
+/// kills of those who went before.
+fn execute_sequential_round(
+    agents: &mut [Agent],
+    command: &Command,
+    causal: &mut CausalMetrics,
+    last_attacker_id: &mut Option<usize>,
+    field: &Battlefield,
+) -> i32 {
     let mut round_damage = 0;
     let mut rng = rand::thread_rng();
     
-    // Sort agents by team to allow coordinated attacks
+    // Sort agents by tactics to allow coordinated attacks
     let mut order: Vec<usize> = (0..agents.len()).collect();
-    order.sort_by_key(|&i| if agents[i].team == Team::Group { 0 } else { 1 });
+    order.sort_by_key(|&i| agents[i].tactics == Tactics::Solo);
     order.shuffle(&mut rng);
 
-    let occupied: Vec<(i32, i32)> = agents
-        .iter()
-        .map(|a| (a.x, a.y))
-        .collect();
+    // Live occupancy: kept up to date as agents move and die during the round
+    let mut occupied = get_occupied_positions(agents);
 
     // Track which agents have already been targeted this round
-    let mut targeted_agents = std::collections::HashSet::new();
+    let mut targeted_agents = HashSet::new();
 
     for i in order {
         if !agents[i].alive {
             continue;
         }
 
-        // Calculate fatigue based on rounds engaged
-        let fatigue_penalty = if causal.rounds_engaged > 0 {
-            // More fatigue for solo agent (gorilla)
-            let base_fatigue = if agents[i].team == Team::Solo { 3 } else { 1 };
-            // Exponential fatigue growth
-            let raw_fatigue = (causal.rounds_engaged as f32).powf(1.2) * base_fatigue as f32;
-            // Cap fatigue at 50% of base stats
-            let max_fatigue = if agents[i].team == Team::Solo {
-                (agents[i].spd / 2) as f32
-            } else {
-                (agents[i].spd / 3) as f32
-            };
-            raw_fatigue.min(max_fatigue) as i32
-        } else {
-            0
-        };
+        match plan_action(agents, i, command, causal.rounds_engaged, &targeted_agents, field, &mut rng) {
+            Action::Hold => {}
+            Action::Slip => causal.slips += 1,
+            Action::Search => agents[i].search(&mut occupied, field.arena, field.effects.move_cost),
+            Action::Move(tx, ty) => {
+                agents[i].move_towards_coords(tx, ty, &mut occupied, field.arena, field.effects.move_cost)
+            }
+            Action::Attack { target, damage, crit } => {
+                if damage == 0 {
+                    continue;
+                }
+                if crit {
+                    causal.total_critical_hits += 1;
+                }
+                round_damage += damage;
+                targeted_agents.insert(target);
 
-        // Apply fatigue effects
-        let effective_speed = (agents[i].spd - fatigue_penalty).max(1);
-        let effective_strength = (agents[i].str_ - (fatigue_penalty * 2)).max(1);
-        let effective_defense = (agents[i].def - (fatigue_penalty * 3)).max(1);
-
-        // If this is a group agent, try to coordinate with nearby allies
-        if agents[i].team == Team::Group {
-            let nearby_allies: Vec<&Agent> = agents.iter()
-                .filter(|a| a.team == Team::Group && a.alive && a.id != agents[i].id)
-                .filter(|a| agents[i].distance_to(a) <= 2)
-                .collect();
-
-            // If we have nearby allies, increase our hit chance and damage
-            let ally_bonus = nearby_allies.len() as i32;
-            let target_id = agents[i].select_target(&agents);
-            if target_id.is_none() {
-                continue;
+                if strike(agents, i, target, damage) {
+                    *last_attacker_id = Some(agents[i].id);
+                    occupied.remove(&(agents[target].x, agents[target].y));
+                }
             }
+        }
+    }
+
+    round_damage
+}
+
+/// Every agent decides from the same start-of-round snapshot, then all attacks
+/// and moves resolve together. Agents killed this round still land their own
+/// blow, so two agents can kill each other. A blow at a target someone else
+/// has already killed this round is wasted, unless the pair were striking each
+/// other. When several agents step into the same free cell the fastest gets
+/// it, with ties broken at random.
+fn execute_simultaneous_round(
+    agents: &mut [Agent],
+    command: &Command,
+    causal: &mut CausalMetrics,
+    last_attacker_id: &mut Option<usize>,
+    field: &Battlefield,
+) -> i32 {
+    let mut rng = rand::thread_rng();
 
-            let target_id = target_id.unwrap();
-            if targeted_agents.contains(&target_id) {
-                continue; // Skip if target was already attacked this round
+    let mut order: Vec<usize> = (0..agents.len()).collect();
+    order.shuffle(&mut rng);
+
+    let snapshot = agents.to_vec();
+    let mut occupied = get_occupied_positions(&snapshot);
+    let actions: Vec<(usize, Action)> = order
+        .into_iter()
+        .filter(|&i| snapshot[i].alive)
+        .map(|i| (i, plan_action(&snapshot, i, command, causal.rounds_engaged, &HashSet::new(), field, &mut rng)))
+        .collect();
+
+    let round_damage = land_blows(agents, &actions, causal, last_attacker_id, &mut occupied);
+
+    // Moves claim free cells; the dead forfeit theirs
+    let mut claims: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    for (i, action) in &actions {
+        if !agents[*i].alive {
+            continue;
+        }
+        if let Action::Slip = action {
+            causal.slips += 1;
+        }
+        let next = match *action {
+            Action::Search => agents[*i].search_step(&occupied, field.arena),
+            Action::Move(tx, ty) => agents[*i].plan_step(tx, ty, &occupied, field.arena),
+            _ => None,
+        };
+        if let Some(cell) = next {
+            let cost = agents[*i].step_cost(cell, field.arena, field.effects.move_cost);
+            if agents[*i].ready_to_move(cost) {
+                claims.entry(cell).or_default().push(*i);
             }
+        }
+    }
+    for (cell, mut claimants) in claims {
+        claimants.shuffle(&mut rng);
+        claimants.sort_by_key(|&i| std::cmp::Reverse(agents[i].spd));
+        causal.contested_moves += (claimants.len() - 1) as u32;
+        agents[claimants[0]].step_to(cell, &mut occupied);
+    }
 
-            let target = agents.iter().find(|a| a.id == target_id).unwrap();
-            let (tx, ty) = (target.x, target.y);
-
-            if agents[i].distance_to(target) > 1 {
-                agents[i].move_towards_coords(tx, ty, &occupied);
-            } else {
-                // More nuanced hit chance calculation with fatigue
-                let base_hit = 60 + (effective_speed - target.spd) * 5;
-                let ally_hit_bonus = ally_bonus * 3;
-                let hit_chance = (base_hit + ally_hit_bonus + rng.gen_range(-10..=10)).clamp(20, 90);
-                let roll: i32 = rng.gen_range(1..=100);
-                
-                if roll <= hit_chance {
-                    // More nuanced damage calculation with fatigue
-                    let base_damage = agents[i].base_damage;
-                    let strength_bonus = effective_strength;
-                    let defense_reduction = effective_defense / (2 + ally_bonus);
-                    let damage = (base_damage + strength_bonus - defense_reduction).max(2);
-                    
-                    // Critical hit calculation with fatigue
-                    let crit_roll: i32 = rng.gen_range(1..=100);
-                    let base_crit = agents[i].crit_chance;
-                    let ally_crit_bonus = ally_bonus * 2;
-                    let crit_threshold = (base_crit + ally_crit_bonus + rng.gen_range(-5..=5)).clamp(1, 95);
-                    
-                    let total_damage = if crit_roll <= crit_threshold {
-                        causal.total_critical_hits += 1;
-                        damage * 2
-                    } else {
-                        damage
-                    };
-
-                    let (attacker, target) = if i < target_id {
-                        let (left, right) = agents.split_at_mut(target_id);
-                        (&mut left[i], &mut right[0])
-                    } else {
-                        let (left, right) = agents.split_at_mut(i);
-                        (&mut right[0], &mut left[target_id])
-                    };
-
-                    target.take_damage(total_damage);
-                    attacker.damage_dealt += total_damage as u32;
-                    round_damage += total_damage;
-                    targeted_agents.insert(target_id);
-
-                    if !target.alive {
-                        *last_attacker_id = Some(attacker.id);
-                    }
-                }
+    round_damage
+}
+
+/// Lands the blows among a simultaneous round's `actions`, in order, against
+/// start-of-round positions. The group still lands at most one blow per
+/// target, as in sequential rounds. Returns the damage dealt.
+fn land_blows(
+    agents: &mut [Agent],
+    actions: &[(usize, Action)],
+    causal: &mut CausalMetrics,
+    last_attacker_id: &mut Option<usize>,
+    occupied: &mut HashSet<(i32, i32)>,
+) -> i32 {
+    let mut round_damage = 0;
+    let aimed: HashMap<usize, usize> = actions
+        .iter()
+        .filter_map(|(i, action)| match *action {
+            Action::Attack { target, damage, .. } if damage > 0 => Some((*i, target)),
+            _ => None,
+        })
+        .collect();
+    let mut targeted_agents = HashSet::new();
+    let mut blows = Vec::new();
+    for (i, action) in actions {
+        if let Action::Attack { target, damage, crit } = *action {
+            if damage == 0 || (agents[*i].tactics == Tactics::Group && targeted_agents.contains(&target)) {
+                continue;
             }
-        } else {
-            // Solo agent (gorilla) behavior with fatigue
-            let target_id = agents[i].select_target(&agents);
-            if target_id.is_none() {
+            if !agents[target].alive && aimed.get(&target) != Some(i) {
                 continue;
             }
+            if crit {
+                causal.total_critical_hits += 1;
+            }
+            round_damage += damage;
+            targeted_agents.insert(target);
+            blows.push((*i, target));
 
-            let target_id = target_id.unwrap();
-            let target = agents.iter().find(|a| a.id == target_id).unwrap();
-            let (tx, ty) = (target.x, target.y);
-
-            if agents[i].distance_to(target) > 1 {
-                agents[i].move_towards_coords(tx, ty, &occupied);
-            } else {
-                // Gorilla-specific hit chance with fatigue
-                let base_hit = 65 + (effective_speed - target.spd) * 8;
-                let hit_chance = (base_hit + rng.gen_range(-15..=15)).clamp(25, 95);
-                let roll: i32 = rng.gen_range(1..=100);
-                
-                if roll <= hit_chance {
-                    // Gorilla-specific damage with fatigue
-                    let base_damage = agents[i].base_damage;
-                    let strength_bonus = effective_strength * 2;
-                    let defense_reduction = effective_defense / 2;
-                    let damage = (base_damage + strength_bonus - defense_reduction).max(5);
-                    
-                    // Gorilla critical hits with fatigue
-                    let crit_roll: i32 = rng.gen_range(1..=100);
-                    let crit_threshold = (agents[i].crit_chance + rng.gen_range(-10..=10)).clamp(1, 95);
-                    
-                    let total_damage = if crit_roll <= crit_threshold {
-                        causal.total_critical_hits += 1;
-                        damage * 2
-                    } else {
-                        damage
-                    };
-
-                    let (attacker, target) = if i < target_id {
-                        let (left, right) = agents.split_at_mut(target_id);
-                        (&mut left[i], &mut right[0])
-                    } else {
-                        let (left, right) = agents.split_at_mut(i);
-                        (&mut right[0], &mut left[target_id])
-                    };
-
-                    target.take_damage(total_damage);
-                    attacker.damage_dealt += total_damage as u32;
-                    round_damage += total_damage;
-                    targeted_agents.insert(target_id);
-
-                    if !target.alive {
-                        *last_attacker_id = Some(attacker.id);
-                    }
-                }
+            if strike(agents, *i, target, damage) {
+                *last_attacker_id = Some(agents[*i].id);
+                occupied.remove(&(agents[target].x, agents[target].y));
             }
         }
     }
+    causal.mutual_kills += blows
+        .iter()
+        .filter(|&&(i, t)| i < t && !agents[i].alive && !agents[t].alive && blows.contains(&(t, i)))
+        .count() as u32;
 
     round_damage
 }
 
-fn summarize_battle(battle_id: usize, agents: &Vec<Agent>, rounds: u32, causal: CausalMetrics) -> BattleResult {
-    let solo_alive = agents.iter().any(|a| a.team == Team::Solo && a.alive);
+/// Most that thermal strain can raise the fatigue cap by.
+const MAX_STRAIN_ON_CAP: f32 = 1.5;
+
+/// Fatigue penalty to speed, strength and defense after `rounds_engaged` rounds of fighting.
+pub(crate) fn fatigue_penalty(agent: &Agent, rounds_engaged: u32, effects: &EnvironmentModifiers) -> i32 {
+    if rounds_engaged == 0 {
+        return 0;
+    }
+    // More fatigue for lone fighters (gorilla)
+    let base_fatigue = if agent.tactics == Tactics::Solo { 3 } else { 1 };
+    // Exponential fatigue growth, sped up by the weather and by temperatures the agent copes badly with
+    let raw_fatigue =
+        (rounds_engaged as f32).powf(1.2) * base_fatigue as f32 * effects.fatigue_multiplier * agent.thermal_strain;
+    // Cap fatigue at 50% of base stats, raised by up to half again under thermal strain
+    let max_fatigue = if agent.tactics == Tactics::Solo {
+        (agent.spd / 2) as f32
+    } else {
+        (agent.spd / 3) as f32
+    } * agent.thermal_strain.min(MAX_STRAIN_ON_CAP);
+    raw_fatigue.min(max_fatigue) as i32
+}
+
+/// Percent of max HP at or below which climbers retreat up out of reach.
+const RETREAT_HP_PERCENT: i32 = 30;
+
+/// Largest elevation difference that blows can still be traded across.
+const MAX_REACH: i32 = 2;
+
+/// Hit chance percentage points per level the attacker stands above its target.
+const HIGH_GROUND_PER_LEVEL: i32 = 5;
+
+/// A badly hurt climber heads for the nearest cell it can see that is out of
+/// reach from the ground, and holds there once it has made it.
+fn retreat(agent: &Agent, arena: &Arena) -> Option<Action> {
+    if !agent.mobility.can_climb() || agent.hp * 100 > agent.max_hp * RETREAT_HP_PERCENT {
+        return None;
+    }
+    let here = (agent.x, agent.y);
+    if arena.elevation_at(here) > MAX_REACH {
+        return Some(Action::Hold);
+    }
+    arena
+        .nearest_high_cell(here, agent.sight_radius, MAX_REACH, &agent.mobility)
+        .map(|(x, y)| Action::Move(x, y))
+}
+
+/// Decides agent `i`'s action from the state in `agents`, rolling any attack.
+pub(crate) fn plan_action(
+    agents: &[Agent],
+    i: usize,
+    command: &Command,
+    rounds_engaged: u32,
+    targeted_agents: &HashSet<usize>,
+    field: &Battlefield,
+    rng: &mut impl Rng,
+) -> Action {
+    let effects = &field.effects;
+    // Wet ground, snow and the like can cost an agent its turn
+    if rng.gen_range(1..=100) <= effects.slip_chance {
+        return Action::Slip;
+    }
+
+    if let Some(action) = retreat(&agents[i], field.arena) {
+        return action;
+    }
+
+    // Calculate fatigue based on rounds engaged
+    let fatigue_penalty = fatigue_penalty(&agents[i], rounds_engaged, effects);
+
+    // Apply fatigue effects
+    let effective_speed = (agents[i].spd - fatigue_penalty).max(1);
+    let effective_strength = (agents[i].str_ - (fatigue_penalty * 2)).max(1);
+    let effective_defense = (agents[i].def - (fatigue_penalty * 3)).max(1);
+
+    let target_id = target_for(agents, i);
+    if target_id.is_none() {
+        return Action::Search;
+    }
+    let (target_id, (tx, ty)) = target_id.unwrap();
+
+    // If this is a group agent, try to coordinate with nearby allies
+    if agents[i].tactics == Tactics::Group && targeted_agents.contains(&target_id) {
+        return Action::Hold; // Skip if target was already attacked this round
+    }
+
+    let target = &agents[target_id];
+    // Head for where the target is believed to be until it is in reach
+    if !target.alive || agents[i].distance_to(target) > 1 {
+        return Action::Move(tx, ty);
+    }
+
+    // Cover and footing where the target stands
+    let terrain_defense = field.arena.terrain_at((target.x, target.y)).defense_modifier();
+    // Nobody lands blows across too great a drop, such as into a treetop
+    let height_gap =
+        field.arena.elevation_at((agents[i].x, agents[i].y)) - field.arena.elevation_at((target.x, target.y));
+    if height_gap.abs() > MAX_REACH {
+        return Action::Hold;
+    }
+    let high_ground = height_gap * HIGH_GROUND_PER_LEVEL;
+
+    let (damage, crit) = if agents[i].tactics == Tactics::Group {
+        // If we have nearby allies, increase our hit chance and damage
+        let ally_bonus = command.coordination_bonus(agents, i);
+
+        // More nuanced hit chance calculation with fatigue
+        let base_hit = 60 + (effective_speed - target.spd) * 5;
+        let ally_hit_bonus = ally_bonus * 3;
+        let hit_chance = (base_hit + ally_hit_bonus + effects.hit_modifier - terrain_defense + high_ground + rng.gen_range(-10..=10)).clamp(20, 90);
+        let roll: i32 = rng.gen_range(1..=100);
+        if roll > hit_chance {
+            return Action::Attack { target: target_id, damage: 0, crit: false };
+        }
+
+        // More nuanced damage calculation with fatigue
+        let base_damage = agents[i].base_damage;
+        let strength_bonus = effective_strength;
+        let defense_reduction = effective_defense / (2 + ally_bonus);
+        let damage = (base_damage + strength_bonus - defense_reduction).max(2);
+
+        // Critical hit calculation with fatigue
+        let crit_roll: i32 = rng.gen_range(1..=100);
+        let base_crit = agents[i].crit_chance;
+        let ally_crit_bonus = ally_bonus * 2;
+        let crit_threshold = (base_crit + ally_crit_bonus + rng.gen_range(-5..=5)).clamp(1, 95);
+        (damage, crit_roll <= crit_threshold)
+    } else {
+        // Gorilla-specific hit chance with fatigue
+        let base_hit = 65 + (effective_speed - target.spd) * 8;
+        let hit_chance = (base_hit + effects.hit_modifier - terrain_defense + high_ground + rng.gen_range(-15..=15)).clamp(25, 95);
+        let roll: i32 = rng.gen_range(1..=100);
+        if roll > hit_chance {
+            return Action::Attack { target: target_id, damage: 0, crit: false };
+        }
+
+        // Gorilla-specific damage with fatigue
+        let base_damage = agents[i].base_damage;
+        let strength_bonus = effective_strength * 2;
+        let defense_reduction = effective_defense / 2;
+        let damage = (base_damage + strength_bonus - defense_reduction).max(5);
+
+        // Gorilla critical hits with fatigue
+        let crit_roll: i32 = rng.gen_range(1..=100);
+        let crit_threshold = (agents[i].crit_chance + rng.gen_range(-10..=10)).clamp(1, 95);
+        (damage, crit_roll <= crit_threshold)
+    };
+
+    let damage = if crit { damage * 2 } else { damage };
+    Action::Attack { target: target_id, damage, crit }
+}
+
+/// Applies a landed blow. Returns true if it killed a target that was alive.
+pub(crate) fn strike(agents: &mut [Agent], i: usize, target_id: usize, damage: i32) -> bool {
+    let (attacker, target) = if i < target_id {
+        let (left, right) = agents.split_at_mut(target_id);
+        (&mut left[i], &mut right[0])
+    } else {
+        let (left, right) = agents.split_at_mut(i);
+        (&mut right[0], &mut left[target_id])
+    };
+
+    let was_alive = target.alive;
+    target.take_damage(damage);
+    attacker.damage_dealt += damage as u32;
+    let killed = was_alive && !target.alive;
+    attacker.kills += killed as u32;
+    killed
+}
+
+/// The alliance left standing once every other has fallen. `None` if no
+/// side is standing, or if the battle was cut off with several still up.
+fn winning_alliance(agents: &[Agent]) -> Option<usize> {
+    let mut standing = agents.iter().filter(|a| a.alive).map(|a| a.alliance);
+    let first = standing.next()?;
+    standing.all(|alliance| alliance == first).then_some(first)
+}
+
+/// Names of the teams in the winning alliance joined by " & ", or the timeout
+/// label for a battle cut off at the round limit with several sides still
+/// up, or the draw label. `teams` gives each team's alliance and name.
+fn winner_label<'a>(agents: &[Agent], rounds: u32, teams: impl Iterator<Item = (usize, &'a str)>) -> String {
+    if let Some(winner) = winning_alliance(agents) {
+        let names: Vec<&str> = teams.filter(|&(alliance, _)| alliance == winner).map(|(_, name)| name).collect();
+        names.join(" & ")
+    } else if rounds >= MAX_ROUNDS && agents.iter().any(|a| a.alive) {
+        TIMEOUT.to_string()
+    } else {
+        DRAW.to_string()
+    }
+}
+
+/// How each profile in a team fared. `profiles` names the profile of each
+/// member of the team in order, its waves included. Members and waves with
+/// the same profile are counted together.
+fn member_results<'a>(profiles: impl Iterator<Item = &'a String>, agents: &[&Agent]) -> Vec<MemberResult> {
+    let mut results: Vec<MemberResult> = Vec::new();
+    for (m, profile) in profiles.enumerate() {
+        let i = match results.iter().position(|r| r.profile == *profile) {
+            Some(i) => i,
+            None => {
+                results.push(MemberResult {
+                    profile: profile.clone(),
+                    count: 0,
+                    survivors: 0,
+                    damage_dealt: 0,
+                });
+                results.len() - 1
+            }
+        };
+        for agent in agents.iter().filter(|a| a.member == m) {
+            results[i].count += 1;
+            results[i].survivors += agent.alive as u32;
+            results[i].damage_dealt += agent.damage_dealt;
+        }
+    }
+    results
+}
+
+fn summarize_battle(
+    battle_id: usize,
+    agents: &[Agent],
+    teams: &[TeamSetup],
+    rounds: u32,
+    context: BattleContext,
+    causal: CausalMetrics,
+    scenario: &Scenario,
+) -> BattleResult {
+    let winner = winning_alliance(agents);
+    let team_results: Vec<TeamResult> = teams
+        .iter()
+        .enumerate()
+        .map(|(t, team)| {
+            let members: Vec<&Agent> = agents.iter().filter(|a| a.team == t).collect();
+            // Reinforcements are numbered after the team's own members
+            let powers: Vec<f64> =
+                members.iter().filter(|a| a.member < team.members.len()).map(|a| a.power()).collect();
+            TeamResult {
+                name: team.config.name.clone(),
+                members: member_results(
+                    team.members
+                        .iter()
+                        .map(|(member, _)| &member.profile)
+                        .chain(team.waves.iter().map(|(wave, _)| &wave.profile)),
+                    &members,
+                ),
+                tactics: team.config.tactics,
+                alliance: team.alliance,
+                count: members.len() as u32,
+                survivors: members.iter().filter(|a| a.alive).count() as u32,
+                damage_dealt: members.iter().map(|a| a.damage_dealt).sum(),
+                formation: team.config.side.formation,
+                won: winner == Some(team.alliance),
+                mean_power: powers.iter().sum::<f64>() / powers.len().max(1) as f64,
+                top_power: powers.iter().copied().fold(0.0, f64::max),
+            }
+        })
+        .collect();
+    let winner_label = winner_label(agents, rounds, team_results.iter().map(|t| (t.alliance, t.name.as_str())));
+    let formation_of = |tactics: Tactics| {
+        teams
+            .iter()
+            .find(|t| t.config.tactics == tactics)
+            .map(|t| t.config.side.formation)
+            .unwrap_or_default()
+    };
+
+    let solos: Vec<SoloResult> = agents
+        .iter()
+        .filter(|a| a.tactics == Tactics::Solo)
+        .map(|a| SoloResult::of(a, &teams[a.team].config.name))
+        .collect();
+    let solos_alive = solos.iter().filter(|s| s.survived).count() as u32;
     let group_casualties = agents
         .iter()
-        .filter(|a| a.team == Team::Group && !a.alive)
+        .filter(|a| a.tactics == Tactics::Group && !a.alive)
         .count() as u32;
 
-    let context = BattleContext::random_from_file("realistic_cities_with_climate.csv");
-
     let mut result = BattleResult {
         battle_id,
-        winner: if solo_alive { Team::Solo } else { Team::Group },
+        winner: winner_label,
         rounds,
         group_casualties,
-        solo_survived: solo_alive,
+        solo_survived: solos_alive > 0,
         context,
         causal,
+        round_mode: scenario.round_mode.clone(),
+        engine: scenario.engine.clone(),
+        elapsed_seconds: rounds as f64 * SECONDS_PER_ROUND,
+        map: String::new(),
+        density: 0.0,
+        group_formation: formation_of(Tactics::Group),
+        solo_formation: formation_of(Tactics::Solo),
+        weather_timeline: Vec::new(),
+        teams: team_results,
+        arrivals: Vec::new(),
+        solos,
+        solos_alive,
+        seed: 0,
     };
 
     // Calculate group average damage and max group damage
     let group_damage: Vec<u32> = agents
         .iter()
-        .filter(|a| a.team == Team::Group)
+        .filter(|a| a.tactics == Tactics::Group)
         .map(|a| a.damage_dealt)
         .collect();
 
@@ -298,10 +1101,135 @@ fn summarize_battle(battle_id: usize, agents: &Vec<Agent>, rounds: u32, causal:
         result.causal.max_group_damage = *group_damage.iter().max().unwrap() as f32;
     }
 
-    // Set solo end HP
-    if let Some(solo) = agents.iter().find(|a| a.team == Team::Solo) {
-        result.causal.solo_end_hp = if solo.alive { solo.hp.max(0) as u32 } else { 0 };
-    }
+    result.causal.solo_end_hp = result.solos.first().map_or(0, |s| s.end_hp);
+    result.causal.solos_total_end_hp = result.solos.iter().map(|s| s.end_hp).sum();
 
     result
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Map;
+
+    /// Six agents of one profile, the last three dead, split between
+    /// `member` numbers by `member_of`.
+    fn team(member_of: impl Fn(usize) -> usize) -> Vec<Agent> {
+        let profiles = ProfileRegistry::resolve("test", &Map::new()).unwrap();
+        let profile = profiles.get("Untrained Man").unwrap();
+        (0..6)
+            .map(|i| {
+                let mut agent = Agent::new_from_profile(i, 0, Tactics::Group, i as i32, 0, profile);
+                agent.member = member_of(i);
+                agent.alive = i < 3;
+                agent.damage_dealt = 10;
+                agent
+            })
+            .collect()
+    }
+
+    /// A fighter of `alliance` with `hp` to spare, standing at x = `id`.
+    fn fighter(id: usize, alliance: usize, tactics: Tactics, hp: i32) -> Agent {
+        Agent { id, alliance, tactics, hp, max_hp: hp, x: id as i32, ..Agent::default() }
+    }
+
+    fn attack(i: usize, target: usize, damage: i32) -> (usize, Action) {
+        (i, Action::Attack { target, damage, crit: false })
+    }
+
+    fn land(agents: &mut [Agent], actions: &[(usize, Action)]) -> (i32, CausalMetrics) {
+        let mut causal = CausalMetrics::new();
+        let mut occupied = agents.iter().map(|a| (a.x, a.y)).collect();
+        let damage = land_blows(agents, actions, &mut causal, &mut None, &mut occupied);
+        (damage, causal)
+    }
+
+    #[test]
+    fn fighters_striking_each_other_both_fall() {
+        let mut agents = vec![fighter(0, 0, Tactics::Solo, 5), fighter(1, 1, Tactics::Solo, 5)];
+        let (damage, causal) = land(&mut agents, &[attack(0, 1, 5), attack(1, 0, 5)]);
+        assert_eq!(damage, 10);
+        assert_eq!(causal.mutual_kills, 1);
+        assert!(agents.iter().all(|a| !a.alive));
+    }
+
+    #[test]
+    fn blows_at_an_agent_already_killed_are_skipped() {
+        let mut agents = vec![
+            fighter(0, 0, Tactics::Solo, 5),
+            fighter(1, 0, Tactics::Solo, 5),
+            fighter(2, 1, Tactics::Solo, 5),
+        ];
+        let (damage, causal) = land(&mut agents, &[attack(0, 2, 5), attack(1, 2, 5)]);
+        assert_eq!(damage, 5);
+        assert_eq!((agents[0].damage_dealt, agents[0].kills), (5, 1));
+        assert_eq!((agents[1].damage_dealt, agents[1].kills), (0, 0));
+        assert_eq!(causal.mutual_kills, 0);
+    }
+
+    #[test]
+    fn the_group_strikes_each_target_once() {
+        let mut agents = vec![
+            fighter(0, 0, Tactics::Group, 5),
+            fighter(1, 0, Tactics::Group, 5),
+            fighter(2, 1, Tactics::Solo, 20),
+        ];
+        let (damage, _) = land(&mut agents, &[attack(0, 2, 3), attack(1, 2, 3)]);
+        assert_eq!(damage, 3);
+        assert_eq!(agents[2].hp, 17);
+    }
+
+    /// Agents of `alliances`, alive or not.
+    fn standing(alliances: &[(usize, bool)]) -> Vec<Agent> {
+        alliances.iter().map(|&(alliance, alive)| Agent { alliance, alive, ..Agent::default() }).collect()
+    }
+
+    fn label(agents: &[Agent], rounds: u32) -> String {
+        let teams = [(0, "Men"), (1, "Gorilla"), (0, "Dogs")];
+        winner_label(agents, rounds, teams.into_iter())
+    }
+
+    #[test]
+    fn every_team_of_the_last_alliance_standing_wins() {
+        let agents = standing(&[(0, true), (0, false), (1, false)]);
+        assert_eq!(label(&agents, 40), "Men & Dogs");
+        assert_eq!(label(&agents, MAX_ROUNDS), "Men & Dogs");
+        assert_eq!(label(&standing(&[(0, false), (1, true)]), 40), "Gorilla");
+    }
+
+    #[test]
+    fn battles_nobody_wins_are_draws_or_timeouts() {
+        assert_eq!(label(&standing(&[(0, false), (1, false)]), 40), DRAW);
+        assert_eq!(label(&standing(&[(0, false), (1, false)]), MAX_ROUNDS), DRAW);
+        // Sides that lose contact end the battle early
+        assert_eq!(label(&standing(&[(0, true), (1, true)]), 400), DRAW);
+        assert_eq!(label(&standing(&[(0, true), (1, true)]), MAX_ROUNDS), TIMEOUT);
+    }
+
+    #[test]
+    fn waves_of_a_members_profile_count_with_it() {
+        let man = "Untrained Man".to_string();
+        let without_waves = team(|_| 0);
+        let with_waves = team(|i| i / 2);
+        let without: Vec<&Agent> = without_waves.iter().collect();
+        let with: Vec<&Agent> = with_waves.iter().collect();
+
+        let plain = member_results([&man].into_iter(), &without);
+        let reinforced = member_results([&man, &man, &man].into_iter(), &with);
+        assert_eq!(reinforced.len(), 1);
+        assert_eq!(
+            (reinforced[0].count, reinforced[0].survivors, reinforced[0].damage_dealt),
+            (plain[0].count, plain[0].survivors, plain[0].damage_dealt)
+        );
+        assert_eq!((plain[0].count, plain[0].survivors), (6, 3));
+    }
+
+    #[test]
+    fn waves_of_another_profile_get_their_own_entry() {
+        let (man, soldier) = ("Untrained Man".to_string(), "Soldier".to_string());
+        let agents = team(|i| i / 3);
+        let agents: Vec<&Agent> = agents.iter().collect();
+        let results = member_results([&man, &soldier].into_iter(), &agents);
+        assert_eq!(results.iter().map(|r| (r.count, r.survivors)).collect::<Vec<_>>(), vec![(3, 3), (3, 0)]);
+    }
+}
@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Days in each month of a non-leap year.
+const MONTH_DAYS: [u32; 12] = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+/// Sun elevation, in degrees, at which light is fully gone or full.
+const DARK_ELEVATION: f64 = -6.0;
+const FULL_LIGHT_ELEVATION: f64 = 6.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum LightPhase {
+    /// Sun above the horizon.
+    #[default]
+    Day,
+    /// Sun less than 6 degrees below the horizon (civil twilight).
+    Twilight,
+    Night,
+}
+
+impl LightPhase {
+    pub fn from_elevation(elevation: f64) -> Self {
+        if elevation > 0.0 {
+            LightPhase::Day
+        } else if elevation > DARK_ELEVATION {
+            LightPhase::Twilight
+        } else {
+            LightPhase::Night
+        }
+    }
+}
+
+/// Ambient light from 0 (dark) to 1 (full daylight), ramping linearly between
+/// 6 degrees below and 6 degrees above the horizon.
+pub fn light_level(elevation: f64) -> f64 {
+    ((elevation - DARK_ELEVATION) / (FULL_LIGHT_ELEVATION - DARK_ELEVATION)).clamp(0.0, 1.0)
+}
+
+/// Solar elevation in degrees at the given position, day of year (1-365) and
+/// UTC hour, using the NOAA fractional-year approximation.
+pub fn solar_elevation(latitude: f64, longitude: f64, day_of_year: u32, utc_hour: f64) -> f64 {
+    let gamma = 2.0 * PI / 365.0 * (day_of_year as f64 - 1.0 + (utc_hour - 12.0) / 24.0);
+
+    // Equation of time in minutes and solar declination in radians
+    let eqtime = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos()
+            - 0.040849 * (2.0 * gamma).sin());
+    let decl = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+
+    let true_solar_minutes = utc_hour * 60.0 + eqtime + 4.0 * longitude;
+    let hour_angle = (true_solar_minutes / 4.0 - 180.0).to_radians();
+    let lat = latitude.to_radians();
+
+    let cos_zenith = (lat.sin() * decl.sin() + lat.cos() * decl.cos() * hour_angle.cos()).clamp(-1.0, 1.0);
+    90.0 - cos_zenith.acos().to_degrees()
+}
+
+/// Converts a local mean solar time at `longitude` to a UTC day and hour.
+pub fn local_to_utc(day_of_year: u32, local_hour: f64, longitude: f64) -> (u32, f64) {
+    let utc_hour = local_hour - longitude / 15.0;
+    if utc_hour < 0.0 {
+        (if day_of_year == 1 { 365 } else { day_of_year - 1 }, utc_hour + 24.0)
+    } else if utc_hour >= 24.0 {
+        (if day_of_year == 365 { 1 } else { day_of_year + 1 }, utc_hour - 24.0)
+    } else {
+        (day_of_year, utc_hour)
+    }
+}
+
+/// Parses "MM-DD" or "YYYY-MM-DD" into a day of year (1-365). The year is
+/// ignored and February 29th is treated as March 1st.
+pub fn parse_date(date: &str) -> Result<u32, String> {
+    let parts: Vec<&str> = date.split('-').collect();
+    let (month, day) = match parts.as_slice() {
+        [month, day] | [_, month, day] => (month, day),
+        _ => return Err(format!("Invalid date '{}', expected MM-DD or YYYY-MM-DD", date)),
+    };
+    let month: usize = month.parse().map_err(|_| format!("Invalid month in date '{}'", date))?;
+    let day: u32 = day.parse().map_err(|_| format!("Invalid day in date '{}'", date))?;
+    if !(1..=12).contains(&month) {
+        return Err(format!("Invalid month in date '{}'", date));
+    }
+    let max_day = if month == 2 { 29 } else { MONTH_DAYS[month - 1] };
+    if !(1..=max_day).contains(&day) {
+        return Err(format!("Invalid day in date '{}'", date));
+    }
+
+    let day_of_year = MONTH_DAYS[..month - 1].iter().sum::<u32>() + day;
+    Ok(day_of_year.min(365))
+}
+
+/// Parses "HH:MM" into fractional hours.
+pub fn parse_time(time: &str) -> Result<f64, String> {
+    let (hours, minutes) = time
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid time '{}', expected HH:MM", time))?;
+    let hours: u32 = hours.parse().map_err(|_| format!("Invalid hour in time '{}'", time))?;
+    let minutes: u32 = minutes.parse().map_err(|_| format!("Invalid minute in time '{}'", time))?;
+    if hours > 23 || minutes > 59 {
+        return Err(format!("Invalid time '{}'", time));
+    }
+    Ok(hours as f64 + minutes as f64 / 60.0)
+}
+
+/// Month (1-12) containing a day of year.
+pub fn month_of(day_of_year: u32) -> u32 {
+    let mut remaining = day_of_year;
+    for (i, days) in MONTH_DAYS.iter().enumerate() {
+        if remaining <= *days {
+            return i as u32 + 1;
+        }
+        remaining -= days;
+    }
+    12
+}
+
+/// Formats a day of year as "MM-DD".
+pub fn format_date(day_of_year: u32) -> String {
+    let month = month_of(day_of_year);
+    let day = day_of_year - MONTH_DAYS[..month as usize - 1].iter().sum::<u32>();
+    format!("{:02}-{:02}", month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_noaa_reference_values() {
+        // NOAA's solar calculator for Greenwich (51.4769 N, 0.0005 W) at 12:00 UTC
+        let greenwich = |day, hour| solar_elevation(51.4769, -0.0005, day, hour);
+        assert!((greenwich(172, 12.0) - 61.97).abs() < 0.1, "{}", greenwich(172, 12.0));
+        assert!((greenwich(355, 12.0) - 15.09).abs() < 0.1, "{}", greenwich(355, 12.0));
+        assert!(greenwich(172, 0.0) < DARK_ELEVATION);
+    }
+
+    #[test]
+    fn parses_dates_with_or_without_a_year() {
+        assert_eq!(parse_date("01-01"), Ok(1));
+        assert_eq!(parse_date("2024-03-01"), Ok(60));
+        assert_eq!(parse_date("12-31"), Ok(365));
+        // February 29th counts as March 1st
+        assert_eq!(parse_date("02-29"), Ok(60));
+        for date in ["13-01", "04-31", "00-10", "06", "2024-06-xx", "1-2-3-4"] {
+            assert!(parse_date(date).is_err(), "{} parsed", date);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::models::Agent;
+
+/// Squadmates within this distance of an agent count towards its coordination bonus.
+pub const COORDINATION_RANGE: i32 = 2;
+
+#[derive(Debug, Clone)]
+pub struct Squad {
+    pub id: usize,
+    /// Index of the leader in the agents vector.
+    pub leader: usize,
+    /// Indices of every member, leader included.
+    pub members: Vec<usize>,
+    /// Target the leader last ordered the squad onto.
+    pub order: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Dispatch {
+    squad: usize,
+    member: usize,
+    target: usize,
+    /// Where the leader saw the target when the order went out.
+    position: (i32, i32),
+    arrives: u32,
+}
+
+/// Chain of command for one battle: the squads and the orders still travelling
+/// from leaders to their members.
+#[derive(Debug, Clone, Default)]
+pub struct Command {
+    pub squads: Vec<Squad>,
+    in_transit: Vec<Dispatch>,
+    pub orders_issued: u32,
+}
+
+impl Command {
+    /// Splits the members of each of `teams` into squads of `squad_size` in
+    /// spawn order. The first member of each squad leads it. A size of 0
+    /// leaves the teams as mobs.
+    pub fn form_squads(agents: &mut [Agent], teams: &[usize], squad_size: usize) -> Self {
+        let mut command = Command::default();
+        for &team in teams {
+            let members: Vec<usize> = (0..agents.len()).filter(|&i| agents[i].team == team).collect();
+            command.add_squads(agents, &members, squad_size);
+        }
+        command
+    }
+
+    /// Splits `members` into new squads of `squad_size`, as for reinforcements
+    /// arriving together. A size of 0 leaves them out of any squad.
+    pub fn add_squads(&mut self, agents: &mut [Agent], members: &[usize], squad_size: usize) {
+        if squad_size == 0 {
+            return;
+        }
+        for chunk in members.chunks(squad_size) {
+            let id = self.squads.len();
+            for &i in chunk {
+                agents[i].squad_id = Some(id);
+            }
+            self.squads.push(Squad {
+                id,
+                leader: chunk[0],
+                members: chunk.to_vec(),
+                order: None,
+            });
+        }
+    }
+
+    /// Living leaders pick the nearest enemy they know of as their squad's target
+    /// and send the order out, then any orders that have reached their members
+    /// take effect. An order also tells the member where the target was seen.
+    pub fn issue_orders(&mut self, agents: &mut [Agent], round: u32) {
+        for squad in &mut self.squads {
+            let leader = &agents[squad.leader];
+            if !leader.alive {
+                continue;
+            }
+            let (target, position) = match leader.perceived_target() {
+                Some((target, position)) if Some(target) != squad.order => (target, position),
+                _ => continue,
+            };
+            squad.order = Some(target);
+            self.orders_issued += 1;
+
+            // A new order supersedes whatever was still on its way
+            self.in_transit.retain(|d| d.squad != squad.id);
+            for &member in &squad.members {
+                if agents[member].alive {
+                    self.in_transit.push(Dispatch {
+                        squad: squad.id,
+                        member,
+                        target,
+                        position,
+                        arrives: round + order_latency(leader, &agents[member]),
+                    });
+                }
+            }
+        }
+
+        let (arrived, pending): (Vec<Dispatch>, Vec<Dispatch>) =
+            self.in_transit.drain(..).partition(|d| d.arrives <= round);
+        self.in_transit = pending;
+        for dispatch in arrived {
+            let member = &mut agents[dispatch.member];
+            if member.alive {
+                member.order_target = Some(dispatch.target);
+                member.memory.insert(dispatch.target, dispatch.position);
+            }
+        }
+    }
+
+    pub fn leader_alive(&self, agents: &[Agent], squad_id: usize) -> bool {
+        agents[self.squads[squad_id].leader].alive
+    }
+
+    pub fn leaders_killed(&self, agents: &[Agent]) -> u32 {
+        self.squads.iter().filter(|s| !agents[s.leader].alive).count() as u32
+    }
+
+    /// Every living ally within range adds 1. Squadmates acting on the same order
+    /// add 2 instead, but only while their leader is alive to coordinate them.
+    pub fn coordination_bonus(&self, agents: &[Agent], i: usize) -> i32 {
+        let agent = &agents[i];
+        let coordinated = agent.squad_id.is_some_and(|s| self.leader_alive(agents, s))
+            && agent.order_target.is_some();
+
+        agents
+            .iter()
+            .filter(|a| a.team == agent.team && a.alive && a.id != agent.id)
+            .filter(|a| agent.distance_to(a) <= COORDINATION_RANGE)
+            .map(|a| {
+                if coordinated && a.squad_id == agent.squad_id && a.order_target == agent.order_target {
+                    2
+                } else {
+                    1
+                }
+            })
+            .sum()
+    }
+}
+
+/// Agents follow their last received order while they still know where its
+/// target is, otherwise they head for the nearest enemy they know of. Returns
+/// the target and the position the agent believes it is at.
+pub fn target_for(agents: &[Agent], i: usize) -> Option<(usize, (i32, i32))> {
+    let agent = &agents[i];
+    agent
+        .order_target
+        .and_then(|t| agent.memory.get(&t).map(|&pos| (t, pos)))
+        .or_else(|| agent.perceived_target())
+}
+
+/// Rounds an order takes to travel from leader to member. Orders cover a few
+/// cells per round, more when both sides have higher `int_abstract`.
+pub fn order_latency(leader: &Agent, member: &Agent) -> u32 {
+    if leader.id == member.id {
+        return 0;
+    }
+    let cells_per_round = ((leader.int_abstract + member.int_abstract) / 8).max(1);
+    1 + (leader.distance_to(member) / cells_per_round) as u32
+}
@@ -0,0 +1,117 @@
+use crate::character_profiles::AgentProfile;
+use serde::{Deserialize, Serialize};
+
+/// Climbing effort spread over `climbing + dexterity` to give the rounds
+/// needed per level climbed.
+const CLIMB_EFFORT: i32 = 20;
+
+/// What covers a cell of the arena.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Terrain {
+    #[default]
+    Open,
+    /// Impassable to everyone.
+    Wall,
+    Water,
+    Mud,
+    Brush,
+    Rock,
+    /// Climbable, and high enough to be out of reach from the ground.
+    Tree,
+    /// Climbable.
+    Boulder,
+}
+
+impl Terrain {
+    /// Parses a map symbol: `.` open, `#` wall, `~` water, `m` mud, `*` brush,
+    /// `^` rock, `T` tree, `B` boulder.
+    pub fn from_symbol(symbol: char) -> Option<Self> {
+        match symbol {
+            '.' => Some(Terrain::Open),
+            '#' => Some(Terrain::Wall),
+            '~' => Some(Terrain::Water),
+            'm' => Some(Terrain::Mud),
+            '*' => Some(Terrain::Brush),
+            '^' => Some(Terrain::Rock),
+            'T' => Some(Terrain::Tree),
+            'B' => Some(Terrain::Boulder),
+            _ => None,
+        }
+    }
+
+    /// Rounds of movement needed to step into a cell of this terrain, before
+    /// weather and any climbing. `None` for walls.
+    pub fn move_cost(self) -> Option<i32> {
+        match self {
+            Terrain::Open | Terrain::Tree | Terrain::Boulder => Some(1),
+            Terrain::Wall => None,
+            Terrain::Water => Some(4),
+            Terrain::Mud => Some(3),
+            Terrain::Brush | Terrain::Rock => Some(2),
+        }
+    }
+
+    /// Percentage points taken off the hit chance of attacks against an agent
+    /// standing here. Negative when the footing leaves it exposed.
+    pub fn defense_modifier(self) -> i32 {
+        match self {
+            Terrain::Open | Terrain::Wall | Terrain::Boulder => 0,
+            Terrain::Water => -10,
+            Terrain::Mud => -5,
+            Terrain::Brush => 10,
+            Terrain::Rock | Terrain::Tree => 5,
+        }
+    }
+
+    /// Height above the ground in levels. Only climbers can reach raised cells.
+    pub fn elevation(self) -> i32 {
+        match self {
+            Terrain::Tree => 3,
+            Terrain::Boulder => 2,
+            _ => 0,
+        }
+    }
+}
+
+/// Where a combatant can go and what climbing costs it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Mobility {
+    /// Terrain it can't enter. Walls are impassable to everyone.
+    pub impassable: Vec<Terrain>,
+    /// Rounds of movement per level climbed, or `None` if it can't climb.
+    pub climb_cost: Option<i32>,
+}
+
+impl Mobility {
+    pub fn from_profile(profile: &AgentProfile) -> Self {
+        let climb_cost = (profile.climbing.value() > 0).then(|| {
+            let skill = (profile.climbing.value() + profile.dexterity.value()).max(1);
+            (CLIMB_EFFORT + skill - 1) / skill
+        });
+        Mobility {
+            impassable: profile.impassable_terrain.clone(),
+            climb_cost,
+        }
+    }
+
+    pub fn can_climb(&self) -> bool {
+        self.climb_cost.is_some()
+    }
+
+    /// Mobility of a mixed group: every terrain any of them can't enter, and
+    /// climbing only if all of them can, at the slowest climber's cost.
+    pub fn common(mobilities: &[Mobility]) -> Self {
+        let mut impassable = Vec::new();
+        for terrain in mobilities.iter().flat_map(|m| &m.impassable) {
+            if !impassable.contains(terrain) {
+                impassable.push(*terrain);
+            }
+        }
+        let climb_cost = mobilities
+            .iter()
+            .map(|m| m.climb_cost)
+            .reduce(|a, b| a.zip(b).map(|(a, b)| a.max(b)))
+            .flatten();
+        Mobility { impassable, climb_cost }
+    }
+}
@@ -0,0 +1,165 @@
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+
+/// Temperatures (°C) outside this band start to add thermal load.
+const HEAT_THRESHOLD: f64 = 25.0;
+const COLD_THRESHOLD: f64 = 5.0;
+
+/// Tolerance that cancels thermal load entirely, on the 0-20 stat scale.
+const MAX_TOLERANCE: f64 = 20.0;
+
+/// Endurance at which thermal load applies at face value.
+const REFERENCE_ENDURANCE: f64 = 10.0;
+
+/// Day of year of the warmest weather in the northern hemisphere.
+const NORTHERN_PEAK_DAY: f64 = 200.0;
+
+/// Local hour of the daily temperature peak.
+const AFTERNOON_PEAK_HOUR: f64 = 15.0;
+
+/// Typical temperatures for a climate label.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClimateTemperature {
+    /// Annual mean at `reference_latitude`, °C.
+    pub annual_mean: f64,
+    /// Half the difference between the warmest and coldest months at `reference_latitude`.
+    pub seasonal_swing: f64,
+    /// Half the difference between afternoon highs and pre-dawn lows.
+    pub daily_swing: f64,
+    pub reference_latitude: f64,
+}
+
+/// Temperatures loaded from `climate_temperatures.json`: each climate's
+/// typical temperatures, and how much warmer (or colder) than usual each
+/// weather makes it, in °C.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TemperatureTable {
+    pub climate: BTreeMap<String, ClimateTemperature>,
+    pub weather: BTreeMap<String, f64>,
+}
+
+impl TemperatureTable {
+    pub fn load(path: &str) -> Self {
+        let file = File::open(path).expect("Could not open climate temperature file");
+        let reader = BufReader::new(file);
+        let table: TemperatureTable = serde_json::from_reader(reader).expect("Failed to deserialize climate temperatures");
+        if let Err(e) = table.validate() {
+            panic!("Invalid climate temperatures {}: {}", path, e);
+        }
+        table
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (climate, t) in &self.climate {
+            if ![t.annual_mean, t.seasonal_swing, t.daily_swing, t.reference_latitude].iter().all(|v| v.is_finite()) {
+                return Err(format!("{}: temperatures must be finite", climate));
+            }
+            if t.seasonal_swing < 0.0 || t.daily_swing < 0.0 {
+                return Err(format!("{}: swings can't be negative", climate));
+            }
+            if !(0.0..=90.0).contains(&t.reference_latitude) {
+                return Err(format!("{}: reference_latitude must be between 0 and 90", climate));
+            }
+        }
+        if let Some((weather, _)) = self.weather.iter().find(|(_, offset)| !offset.is_finite()) {
+            return Err(format!("{}: offset must be finite", weather));
+        }
+        Ok(())
+    }
+
+    /// The first of `climates` with no entry, if any.
+    pub fn missing<'a>(&self, mut climates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+        climates.find(|climate| !self.climate.contains_key(*climate))
+    }
+
+    /// How much warmer (or colder) than usual a weather makes it, in °C.
+    /// Weather without an entry leaves the temperature as it is.
+    pub fn weather_offset(&self, weather: &str) -> f64 {
+        self.weather.get(weather).copied().unwrap_or(0.0)
+    }
+
+    /// Samples the ambient temperature in °C. Starts from the climate's annual
+    /// mean, cooled further from the equator, then adds the season for the
+    /// hemisphere, the time of day, the weather and some day-to-day noise.
+    /// Panics if `climate` has no entry; check a batch's cities with `missing`.
+    pub fn sample(
+        &self,
+        climate: &str,
+        weather: &str,
+        latitude: f64,
+        day_of_year: u32,
+        local_hour: f64,
+        rng: &mut impl Rng,
+    ) -> f64 {
+        let profile = self
+            .climate
+            .get(climate)
+            .unwrap_or_else(|| panic!("No temperatures for climate {}", climate));
+        let mean = profile.annual_mean - 0.4 * (latitude.abs() - profile.reference_latitude);
+
+        // Seasons flip south of the equator and fade towards it
+        let peak_day = if latitude >= 0.0 { NORTHERN_PEAK_DAY } else { NORTHERN_PEAK_DAY - 182.5 };
+        let seasonality = (latitude.abs() / profile.reference_latitude.max(1.0)).min(1.5);
+        let season = profile.seasonal_swing * seasonality * (2.0 * PI * (day_of_year as f64 - peak_day) / 365.0).cos();
+
+        let daily = profile.daily_swing * (2.0 * PI * (local_hour - AFTERNOON_PEAK_HOUR) / 24.0).cos();
+
+        // Box-Muller normal noise with a 3°C standard deviation
+        let (u1, u2): (f64, f64) = (rng.gen_range(f64::EPSILON..1.0), rng.gen());
+        let noise = 3.0 * (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos();
+
+        mean + season + daily + self.weather_offset(weather) + noise
+    }
+}
+
+/// Multiplier on fatigue build-up from heat or cold. Load grows with every
+/// degree past the comfort band, is cut by the matching tolerance and hits
+/// low-endurance agents harder. 1.0 means no thermal stress.
+pub fn thermal_strain(temperature: f64, endurance: i32, heat_tolerance: i32, cold_tolerance: i32) -> f32 {
+    let heat_load = (temperature - HEAT_THRESHOLD).max(0.0) / 10.0;
+    let cold_load = (COLD_THRESHOLD - temperature).max(0.0) / 10.0;
+    let heat = heat_load * (1.0 - heat_tolerance as f64 / MAX_TOLERANCE).max(0.0);
+    let cold = cold_load * (1.0 - cold_tolerance as f64 / MAX_TOLERANCE).max(0.0);
+    let endurance_factor = REFERENCE_ENDURANCE / endurance.max(1) as f64;
+    (1.0 + (heat + cold) * endurance_factor) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn table() -> TemperatureTable {
+        serde_json::from_str(
+            r#"{
+                "climate": { "Arid": { "annual_mean": 24.0, "seasonal_swing": 9.0, "daily_swing": 8.0, "reference_latitude": 25.0 } },
+                "weather": { "Hot": 6.0 }
+            }"#,
+        )
+        .expect("test table parses")
+    }
+
+    #[test]
+    fn weather_shifts_the_temperature() {
+        let table = table();
+        assert!(table.validate().is_ok());
+        assert_eq!(table.weather_offset("Hot"), 6.0);
+        assert_eq!(table.weather_offset("Windy"), 0.0);
+        let sample = |weather| table.sample("Arid", weather, 25.0, 200, 15.0, &mut StdRng::seed_from_u64(1));
+        assert!((sample("Hot") - sample("Clear") - 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reports_climates_without_temperatures() {
+        let table = table();
+        assert_eq!(table.missing(["Arid", "Temperate"].into_iter()), Some("Temperate"));
+        assert_eq!(table.missing(["Arid"].into_iter()), None);
+    }
+}
@@ -0,0 +1,260 @@
+use crate::environment::{AppliedModifiers, BattleContext, ModifierTable};
+use crate::solar::month_of;
+use crate::thermal::TemperatureTable;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+
+/// Simulated seconds between steps of the weather chain.
+pub const WEATHER_STEP_SECONDS: f64 = 60.0;
+
+/// Relative weight of each weather. Weights needn't add up to 1.
+pub type WeatherWeights = BTreeMap<String, f64>;
+
+/// How likely each weather is at the start of a battle in one climate.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClimateWeather {
+    pub weights: WeatherWeights,
+    /// Weights that replace `weights` in particular calendar months, keyed 1 to 12.
+    #[serde(default)]
+    pub months: BTreeMap<u32, WeatherWeights>,
+}
+
+/// Opening weather odds by climate label. Loaded from `weather_distributions.json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WeatherDistributions(BTreeMap<String, ClimateWeather>);
+
+impl WeatherDistributions {
+    pub fn load(path: &str) -> Self {
+        let file = File::open(path).expect("Could not open weather distribution file");
+        let reader = BufReader::new(file);
+        let distributions: WeatherDistributions =
+            serde_json::from_reader(reader).expect("Failed to deserialize weather distributions");
+        if let Err(e) = distributions.check_weights() {
+            panic!("Invalid weather distributions {}: {}", path, e);
+        }
+        distributions
+    }
+
+    fn check_weights(&self) -> Result<(), String> {
+        let check = |label: String, weights: &WeatherWeights| {
+            if weights.values().any(|&w| !(w >= 0.0 && w.is_finite())) {
+                return Err(format!("{}: weights must be finite and not negative", label));
+            }
+            if weights.values().sum::<f64>() <= 0.0 {
+                return Err(format!("{}: needs at least one weather with a positive weight", label));
+            }
+            Ok(())
+        };
+        for (climate, odds) in &self.0 {
+            check(climate.clone(), &odds.weights)?;
+            for (month, weights) in &odds.months {
+                if !(1..=12).contains(month) {
+                    return Err(format!("{}: month {} isn't between 1 and 12", climate, month));
+                }
+                check(format!("{} month {}", climate, month), weights)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the weights, that each of `climates` has odds, and that every
+    /// weather a battle can open with has a row in `chains` to change from.
+    pub fn validate<'a>(&self, chains: &WeatherChains, climates: impl IntoIterator<Item = &'a str>) -> Result<(), String> {
+        self.check_weights()?;
+        if let Some(climate) = climates.into_iter().find(|climate| !self.0.contains_key(*climate)) {
+            return Err(format!("no weather odds for climate {}", climate));
+        }
+        for (climate, odds) in &self.0 {
+            let openings = std::iter::once(&odds.weights).chain(odds.months.values());
+            for (weather, _) in openings.flat_map(|weights| weights.iter()).filter(|(_, &w)| w > 0.0) {
+                if !chains.has_row(climate, weather) {
+                    return Err(format!("{} can open with {}, which has no row in the weather transitions", climate, weather));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws the opening weather for a battle in `climate` on `day_of_year`.
+    /// Panics if the climate has no odds; `validate` checks for that.
+    pub fn sample(&self, climate: &str, day_of_year: u32, rng: &mut impl Rng) -> String {
+        let odds = self
+            .0
+            .get(climate)
+            .unwrap_or_else(|| panic!("No weather odds for climate {}", climate));
+        let weights = odds.months.get(&month_of(day_of_year)).unwrap_or(&odds.weights);
+        let choices: Vec<(&String, &f64)> = weights.iter().collect();
+        let (weather, _) = choices
+            .choose_weighted(rng, |(_, weight)| **weight)
+            .expect("weights are checked when loading");
+        weather.to_string()
+    }
+}
+
+/// Markov chains of weather by climate: for each weather, the chance of each
+/// weather one step later. Loaded from `weather_transitions.json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WeatherChains(BTreeMap<String, BTreeMap<String, BTreeMap<String, f64>>>);
+
+impl WeatherChains {
+    pub fn load(path: &str) -> Self {
+        let file = File::open(path).expect("Could not open weather transition file");
+        let reader = BufReader::new(file);
+        let chains: WeatherChains = serde_json::from_reader(reader).expect("Failed to deserialize weather transitions");
+        if let Err(e) = chains.validate() {
+            panic!("Invalid weather transitions {}: {}", path, e);
+        }
+        chains
+    }
+
+    /// Every row must be a probability distribution over weathers that have
+    /// rows of their own, so a chain can never get stuck on an unknown state.
+    pub fn validate(&self) -> Result<(), String> {
+        for (climate, chain) in &self.0 {
+            for (from, row) in chain {
+                if row.values().any(|&p| !(0.0..=1.0).contains(&p)) {
+                    return Err(format!("{} {}: probabilities must be between 0 and 1", climate, from));
+                }
+                let total: f64 = row.values().sum();
+                if (total - 1.0).abs() > 1e-6 {
+                    return Err(format!("{} {}: probabilities add up to {}, not 1", climate, from, total));
+                }
+                if let Some(to) = row.keys().find(|to| !chain.contains_key(*to)) {
+                    return Err(format!("{} {}: leads to {} which has no row", climate, from, to));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `weather` has a row in `climate`'s chain.
+    pub fn has_row(&self, climate: &str, weather: &str) -> bool {
+        self.0.get(climate).is_some_and(|chain| chain.contains_key(weather))
+    }
+
+    /// Weather one step after `weather`. Weather the chain doesn't know stays put.
+    pub fn next(&self, climate: &str, weather: &str, rng: &mut impl Rng) -> String {
+        let Some(row) = self.0.get(climate).and_then(|chain| chain.get(weather)) else {
+            return weather.to_string();
+        };
+        let mut roll = rng.gen::<f64>();
+        for (to, p) in row {
+            if roll < *p {
+                return to.clone();
+            }
+            roll -= p;
+        }
+        weather.to_string()
+    }
+}
+
+/// The weather from some point in a battle onwards.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeatherChange {
+    /// Simulated seconds since the battle started.
+    pub seconds: f64,
+    pub weather: String,
+}
+
+/// Conditions as they evolve over one battle.
+pub struct Conditions<'a> {
+    chains: &'a WeatherChains,
+    table: &'a ModifierTable,
+    temperatures: &'a TemperatureTable,
+    /// The battle context with the current weather and temperature.
+    pub context: BattleContext,
+    pub applied: AppliedModifiers,
+    /// Starts with the opening weather at 0 seconds.
+    pub timeline: Vec<WeatherChange>,
+}
+
+impl<'a> Conditions<'a> {
+    pub fn new(
+        chains: &'a WeatherChains,
+        table: &'a ModifierTable,
+        temperatures: &'a TemperatureTable,
+        context: BattleContext,
+    ) -> Self {
+        let timeline = vec![WeatherChange {
+            seconds: 0.0,
+            weather: context.weather.clone(),
+        }];
+        Conditions {
+            chains,
+            table,
+            temperatures,
+            applied: table.modifiers_for(&context),
+            context,
+            timeline,
+        }
+    }
+
+    /// Steps the weather chain at `seconds` into the battle. On a change the
+    /// temperature shifts with the weather and the modifiers are recomputed.
+    /// Returns whether the weather changed.
+    pub fn step(&mut self, seconds: f64, rng: &mut impl Rng) -> bool {
+        let next = self.chains.next(&self.context.climate, &self.context.weather, rng);
+        if next == self.context.weather {
+            return false;
+        }
+        self.context.temperature_c +=
+            self.temperatures.weather_offset(&next) - self.temperatures.weather_offset(&self.context.weather);
+        self.context.weather = next;
+        self.applied = self.table.modifiers_for(&self.context);
+        self.timeline.push(WeatherChange {
+            seconds,
+            weather: self.context.weather.clone(),
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chains(json: &str) -> WeatherChains {
+        serde_json::from_str(json).expect("test chains parse")
+    }
+
+    #[test]
+    fn rows_must_sum_to_one() {
+        let valid = chains(r#"{ "Temperate": { "Clear": { "Clear": 0.9, "Rain": 0.1 }, "Rain": { "Rain": 1.0 } } }"#);
+        assert!(valid.validate().is_ok());
+
+        let short = chains(r#"{ "Temperate": { "Clear": { "Clear": 0.7, "Rain": 0.1 }, "Rain": { "Rain": 1.0 } } }"#);
+        let error = short.validate().unwrap_err();
+        assert!(error.contains("Temperate Clear"), "{}", error);
+
+        let over = chains(r#"{ "Temperate": { "Clear": { "Clear": 0.9, "Rain": 0.2 }, "Rain": { "Rain": 1.0 } } }"#);
+        assert!(over.validate().is_err());
+    }
+
+    #[test]
+    fn rows_must_lead_to_known_weather() {
+        let dangling = chains(r#"{ "Arid": { "Clear": { "Clear": 0.5, "Sandstorm": 0.5 } } }"#);
+        assert!(dangling.validate().unwrap_err().contains("Sandstorm"));
+    }
+
+    #[test]
+    fn climates_need_odds_and_openings_need_rows() {
+        let no_snow = chains(r#"{ "Temperate": { "Clear": { "Clear": 1.0 } } }"#);
+        let odds: WeatherDistributions = serde_json::from_str(
+            r#"{ "Temperate": { "weights": { "Clear": 1.0, "Fog": 0.0 }, "months": { "1": { "Snow": 1.0 } } } }"#,
+        )
+        .expect("test odds parse");
+
+        let error = odds.validate(&no_snow, ["Temperate"]).unwrap_err();
+        assert!(error.contains("Snow"), "{}", error);
+
+        let error = odds.validate(&no_snow, ["Temperate", "Polar"]).unwrap_err();
+        assert!(error.contains("Polar"), "{}", error);
+
+        let with_snow = chains(r#"{ "Temperate": { "Clear": { "Clear": 1.0 }, "Snow": { "Snow": 1.0 } } }"#);
+        assert!(odds.validate(&with_snow, ["Temperate"]).is_ok());
+    }
+}